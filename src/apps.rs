@@ -0,0 +1,138 @@
+use crate::launcher::{CustomCommandLauncher, Launcher, LauncherConfig};
+use crate::template::{expand, expand_all, LAUNCH_PLACEHOLDERS};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AppConfig {
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    mime_types: Vec<String>,
+    // uses system.launcher if no program is given
+    #[serde(default)]
+    program: Option<String>,
+    #[serde(default = "default_args")]
+    args: Vec<String>,
+    // used instead of args if the link asks for a page, e.g. ["--page", "{{page}}", "{{path}}"]
+    #[serde(default)]
+    page_args: Vec<String>,
+}
+
+fn default_args() -> Vec<String> {
+    vec!["{{path}}".to_string()]
+}
+
+pub fn default_apps() -> Vec<AppConfig> {
+    vec![AppConfig {
+        extensions: vec!["pdf".to_string()],
+        mime_types: vec!["application/pdf".to_string()],
+        program: None,
+        args: default_args(),
+        page_args: vec![],
+    }]
+}
+
+pub fn mime_type(extension: &str) -> Option<&'static str> {
+    let mime = match extension.to_lowercase().as_str() {
+        "pdf" => "application/pdf",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "ods" => "application/vnd.oasis.opendocument.spreadsheet",
+        "odp" => "application/vnd.oasis.opendocument.presentation",
+        "epub" => "application/epub+zip",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+fn matches_mime_type(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(kind) => mime.split('/').next() == Some(kind),
+        None => pattern.eq_ignore_ascii_case(mime),
+    }
+}
+
+impl AppConfig {
+    pub fn expand_placeholders(&mut self) -> Result<(), String> {
+        if let Some(program) = &self.program {
+            self.program = Some(expand(program, &[])?);
+        }
+        expand_all(&mut self.args, LAUNCH_PLACEHOLDERS)?;
+        expand_all(&mut self.page_args, LAUNCH_PLACEHOLDERS)
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let extension = match path.extension().and_then(|e| e.to_str()) {
+            Some(extension) => extension,
+            None => return false,
+        };
+        if self
+            .extensions
+            .iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(extension))
+        {
+            return true;
+        }
+        match mime_type(extension) {
+            Some(mime) => self.mime_types.iter().any(|m| matches_mime_type(m, mime)),
+            None => false,
+        }
+    }
+
+    pub fn launcher(&self, fallback: &LauncherConfig) -> Box<dyn Launcher> {
+        match &self.program {
+            Some(program) => Box::new(CustomCommandLauncher::new(
+                program,
+                self.args.clone(),
+                self.page_args.clone(),
+            )),
+            None => fallback.build(),
+        }
+    }
+}
+
+pub fn find_app<'a>(apps: &'a [AppConfig], path: &Path) -> Option<&'a AppConfig> {
+    apps.iter().find(|app| app.matches(path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_app, AppConfig};
+    use std::path::Path;
+
+    #[test]
+    fn finds_app_by_extension_or_mime_type() {
+        let apps: Vec<AppConfig> = serde_yaml::from_str(
+            r#"
+- extensions: [pdf]
+  program: xodo
+  args: ["--page", "1", "{{path}}"]
+- mime_types: ["application/vnd.openxmlformats-officedocument.wordprocessingml.document", "text/*"]
+  program: libreoffice
+"#,
+        )
+        .unwrap();
+        let program =
+            |path: &str| find_app(&apps, Path::new(path)).and_then(|app| app.program.clone());
+        assert_eq!(program("lecture.PDF"), Some("xodo".to_string()));
+        assert_eq!(program("notes.docx"), Some("libreoffice".to_string()));
+        assert_eq!(program("notes.md"), Some("libreoffice".to_string()));
+        assert_eq!(program("setup.exe"), None);
+        assert_eq!(program("no_extension"), None);
+    }
+}
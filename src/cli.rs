@@ -0,0 +1,313 @@
+use crate::config::Layers;
+use crate::linker::Linker;
+use crate::service::Service;
+use crate::shutdown::install_signal_handlers;
+
+pub const USAGE: &str = "usage: xodo-linker [options] <command>
+
+options:
+    --config <path>       configuration file. otherwise $XODO_LINKER_CONFIG, the user's config
+                          directory (xodo-linker/config.yaml) or config.yaml next to the executable
+    --set <key>=<value>   override a value, e.g. --set server.port=8080. applied after the
+                          XODO_LINKER__SERVER__PORT=8080 style environment variables
+    --allow-default-config
+                          use the built-in defaults if the configuration is missing or invalid.
+                          check-config ignores it and fails on an invalid configuration
+
+commands:
+    serve                 start the server (default)
+    open <path>           open a file relative to base_path, without going through http
+    check-config          validate the configuration and print it with all defaults applied
+    resolve <url>         show the path, matching rule and launcher for a url without launching
+    link <path> [--expires-in <seconds>]
+                          create a signed link, needs security.secret
+    install-hostname      point system.hostname to this machine in the hosts file, needs admin rights.
+                          the previous file is kept as <hosts file>.bak
+    uninstall-hostname    remove the entry added by install-hostname
+    gen-cert              create a self-signed certificate for system.hostname and localhost at the
+                          paths of server.tls, needs a build with --features tls";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Serve,
+    Open(String),
+    CheckConfig,
+    Resolve(String),
+    Link {
+        path: String,
+        expires_in: Option<u64>,
+    },
+    InstallHostname,
+    UninstallHostname,
+    GenCert,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub config: Option<String>,
+    pub overrides: Vec<(String, String)>,
+    pub allow_default_config: bool,
+    pub command: Command,
+}
+
+impl Cli {
+    // args without the program name. --config may be given before or after the command
+    pub fn parse(args: &[String]) -> Result<Cli, String> {
+        let mut config = None;
+        let mut overrides = vec![];
+        let mut allow_default_config = false;
+        let mut expires_in = None;
+        let mut positional = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" | "-c" => {
+                    config = Some(args.next().ok_or("--config needs a path")?.clone());
+                }
+                "--set" => {
+                    let set = args.next().ok_or("--set needs a key=value")?;
+                    let (key, value) = set
+                        .split_once('=')
+                        .ok_or(format!("--set needs a key=value, got {}", set))?;
+                    overrides.push((key.to_string(), value.to_string()));
+                }
+                "--expires-in" => {
+                    let seconds = args
+                        .next()
+                        .ok_or("--expires-in needs a number of seconds")?;
+                    expires_in = Some(
+                        seconds
+                            .parse()
+                            .map_err(|_| format!("invalid number of seconds: {}", seconds))?,
+                    );
+                }
+                "--allow-default-config" => allow_default_config = true,
+                "--help" | "-h" => positional.insert(0, "help"),
+                arg if arg.starts_with('-') && arg.len() > 1 => {
+                    return Err(format!("unknown option {}", arg))
+                }
+                arg => positional.push(arg),
+            }
+        }
+
+        let argument = |name: &str| match positional.as_slice() {
+            [_, argument] => Ok(argument.to_string()),
+            [_] => Err(format!("{} needs an argument", name)),
+            _ => Err(format!("too many arguments for {}", name)),
+        };
+        let command = match positional.as_slice() {
+            [] | ["serve"] => Command::Serve,
+            ["help", ..] => Command::Help,
+            ["open", ..] => Command::Open(argument("open")?),
+            ["check-config"] => Command::CheckConfig,
+            ["install-hostname"] => Command::InstallHostname,
+            ["uninstall-hostname"] => Command::UninstallHostname,
+            ["gen-cert"] => Command::GenCert,
+            ["resolve", ..] => Command::Resolve(argument("resolve")?),
+            ["link", ..] => Command::Link {
+                path: argument("link")?,
+                expires_in,
+            },
+            [command @ ("serve" | "check-config" | "install-hostname" | "uninstall-hostname"
+            | "gen-cert"), ..] => return Err(format!("too many arguments for {}", command)),
+            [command, ..] => return Err(format!("unknown command {}", command)),
+        };
+        if expires_in.is_some() && !matches!(command, Command::Link { .. }) {
+            return Err("--expires-in only applies to link".to_string());
+        }
+        Ok(Cli {
+            config,
+            overrides,
+            allow_default_config,
+            command,
+        })
+    }
+
+    // the exit code of the process
+    pub fn run(self) -> i32 {
+        let layers = Layers::discover(self.config.as_deref(), self.overrides);
+        let linker = match self.command {
+            Command::Help => {
+                println!("{}", USAGE);
+                return 0;
+            }
+            // check-config reports an invalid configuration instead of falling back to the defaults
+            Command::CheckConfig => match Linker::load_config(&layers) {
+                Ok(linker) => linker,
+                Err(err) => {
+                    println!("{}", err);
+                    println!("# the configuration is invalid");
+                    return 1;
+                }
+            },
+            _ => match Linker::read_config(&layers, self.allow_default_config) {
+                Ok(linker) => linker,
+                Err(err) => {
+                    println!("{}", err);
+                    return 1;
+                }
+            },
+        };
+        match self.command {
+            Command::Help => 0,
+            Command::Serve => {
+                install_signal_handlers();
+                match Service::new(linker, Some(layers)).start() {
+                    Ok(()) => 0,
+                    Err(err) => {
+                        println!("{}", err);
+                        1
+                    }
+                }
+            }
+            Command::Open(path) => match linker.open(&path) {
+                Ok(path_to_file) => {
+                    println!("opened {}", path_to_file);
+                    0
+                }
+                Err(err) => {
+                    println!("could not open {}: {}", path, err);
+                    1
+                }
+            },
+            Command::CheckConfig => {
+                match &layers.file {
+                    Some(path) => println!("# configuration file: {}", path.display()),
+                    None => println!("# no configuration file found, using the defaults"),
+                }
+                let (_, sources) = layers.merge().unwrap_or_default();
+                match linker.effective_config() {
+                    Ok(config) => print!("{}", sources.describe(&config)),
+                    Err(err) => println!("{}", err),
+                }
+                println!("# the configuration is valid");
+                0
+            }
+            Command::Resolve(url) => match linker.resolve(&url) {
+                Ok(resolution) => {
+                    println!("url: {}\n{}", url, resolution);
+                    0
+                }
+                Err(err) => {
+                    println!("{}: {}", url, err);
+                    1
+                }
+            },
+            Command::Link { path, expires_in } => match linker.signed_link(&path, expires_in) {
+                Ok(link) => {
+                    println!("{}", link);
+                    0
+                }
+                Err(err) => {
+                    println!("could not create link: {}", err);
+                    1
+                }
+            },
+            Command::InstallHostname => match linker.install_hostname() {
+                Ok(true) => {
+                    println!("added the hostname to the hosts file");
+                    0
+                }
+                Ok(false) => {
+                    println!("the hostname is already in the hosts file");
+                    0
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    1
+                }
+            },
+            Command::GenCert => match linker.gen_cert() {
+                Ok(tls) => {
+                    println!("created {} and {}", tls.cert, tls.key);
+                    println!("add the certificate to the trusted root certificates of the system or browser");
+                    0
+                }
+                Err(err) => {
+                    println!("could not create certificate: {}", err);
+                    1
+                }
+            },
+            Command::UninstallHostname => match linker.uninstall_hostname() {
+                Ok(true) => {
+                    println!("removed the hostname from the hosts file");
+                    0
+                }
+                Ok(false) => {
+                    println!("the hostname was not in the hosts file");
+                    0
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    1
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cli, Command};
+
+    fn parse(args: &str) -> Result<Cli, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        Cli::parse(&args)
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("").unwrap().command, Command::Serve);
+        assert_eq!(
+            parse("serve --config /etc/xodo.yaml --allow-default-config").unwrap(),
+            Cli {
+                config: Some("/etc/xodo.yaml".to_string()),
+                overrides: vec![],
+                allow_default_config: true,
+                command: Command::Serve
+            }
+        );
+        assert_eq!(
+            parse("--config x.yaml open course/x.pdf --set server.port=8080").unwrap(),
+            Cli {
+                config: Some("x.yaml".to_string()),
+                overrides: vec![("server.port".to_string(), "8080".to_string())],
+                allow_default_config: false,
+                command: Command::Open("course/x.pdf".to_string())
+            }
+        );
+        assert_eq!(parse("check-config").unwrap().command, Command::CheckConfig);
+        assert_eq!(
+            parse("resolve /x.pdf#page=3").unwrap().command,
+            Command::Resolve("/x.pdf#page=3".to_string())
+        );
+        assert_eq!(
+            parse("link x.pdf --expires-in 60").unwrap().command,
+            Command::Link {
+                path: "x.pdf".to_string(),
+                expires_in: Some(60)
+            }
+        );
+        assert_eq!(
+            parse("install-hostname").unwrap().command,
+            Command::InstallHostname
+        );
+        assert_eq!(parse("gen-cert").unwrap().command, Command::GenCert);
+        assert_eq!(parse("resolve -h").unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse("open").is_err());
+        assert!(parse("open a b").is_err());
+        assert!(parse("serve now").is_err());
+        assert!(parse("uninstall-hostname xodo").is_err());
+        assert!(parse("launch x.pdf").is_err());
+        assert!(parse("serve --config").is_err());
+        assert!(parse("serve --port 80").is_err());
+        assert!(parse("serve --set server.port").is_err());
+        assert!(parse("link x.pdf --expires-in soon").is_err());
+        assert!(parse("open x.pdf --expires-in 60").is_err());
+    }
+}
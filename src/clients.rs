@@ -0,0 +1,181 @@
+use crate::rules::Action;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::net::IpAddr;
+
+// 192.168.10.0/24 or ::1/128. a plain address is the same as a /32 or /128
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+// written as "- allow: 127.0.0.0/8" or "- deny: 192.168.10.0/24"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "ClientRuleEntry", into = "ClientRuleEntry")]
+pub struct ClientRule {
+    action: Action,
+    cidr: Cidr,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientRuleEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allow: Option<Cidr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deny: Option<Cidr>,
+}
+
+impl TryFrom<ClientRuleEntry> for ClientRule {
+    type Error = String;
+
+    fn try_from(entry: ClientRuleEntry) -> Result<Self, Self::Error> {
+        match (entry.allow, entry.deny) {
+            (Some(cidr), None) => Ok(ClientRule {
+                action: Action::Allow,
+                cidr,
+            }),
+            (None, Some(cidr)) => Ok(ClientRule {
+                action: Action::Deny,
+                cidr,
+            }),
+            _ => Err("a client rule needs exactly one of allow or deny".to_string()),
+        }
+    }
+}
+
+impl From<ClientRule> for ClientRuleEntry {
+    fn from(rule: ClientRule) -> Self {
+        match rule.action {
+            Action::Allow => ClientRuleEntry {
+                allow: Some(rule.cidr),
+                deny: None,
+            },
+            Action::Deny => ClientRuleEntry {
+                allow: None,
+                deny: Some(rule.cidr),
+            },
+        }
+    }
+}
+
+impl Cidr {
+    pub fn parse(cidr: &str) -> Result<Cidr, String> {
+        let (addr, prefix) = cidr.split_once('/').unwrap_or((cidr, ""));
+        let addr: IpAddr = addr
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|e| format!("invalid address in {}: {}", cidr, e))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            "" => max_prefix,
+            prefix => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or(format!("invalid prefix length in {}", cidr))?,
+        };
+        Ok(Cidr { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // clients of a dual stack socket show up as ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl Serialize for Cidr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cidr = String::deserialize(deserializer)?;
+        Cidr::parse(&cidr).map_err(de::Error::custom)
+    }
+}
+
+// what force_loopback: true stands for
+pub fn loopback_rules() -> Vec<ClientRule> {
+    ["127.0.0.0/8", "::1/128"]
+        .iter()
+        .map(|cidr| ClientRule {
+            action: Action::Allow,
+            cidr: Cidr::parse(cidr).expect("expected preprogrammed cidr to be ok"),
+        })
+        .collect()
+}
+
+// first match wins, clients that match no rule are denied
+pub fn allow_client(rules: &[ClientRule], ip: IpAddr) -> bool {
+    match rules.iter().find(|rule| rule.cidr.contains(ip)) {
+        Some(rule) => rule.action == Action::Allow,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{allow_client, loopback_rules, Cidr, ClientRule};
+
+    #[test]
+    fn matches_cidr_ranges() {
+        let lan = Cidr::parse("192.168.10.0/24").unwrap();
+        assert!(lan.contains("192.168.10.42".parse().unwrap()));
+        assert!(lan.contains("::ffff:192.168.10.42".parse().unwrap()));
+        assert!(!lan.contains("192.168.11.1".parse().unwrap()));
+        assert!(!lan.contains("fe80::1".parse().unwrap()));
+
+        let any = Cidr::parse("0.0.0.0/0").unwrap();
+        assert!(any.contains("8.8.8.8".parse().unwrap()));
+
+        let link_local = Cidr::parse("[fe80::]/10").unwrap();
+        assert!(link_local.contains("fe80::1234".parse().unwrap()));
+        assert!(!link_local.contains("::1".parse().unwrap()));
+
+        assert_eq!(Cidr::parse("::1").unwrap().to_string(), "::1/128");
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("localhost/8").is_err());
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules: Vec<ClientRule> = serde_yaml::from_str(
+            "[deny: 192.168.10.13, allow: 192.168.10.0/24, allow: \"::1/128\"]",
+        )
+        .unwrap();
+        assert!(allow_client(&rules, "192.168.10.12".parse().unwrap()));
+        assert!(!allow_client(&rules, "192.168.10.13".parse().unwrap()));
+        assert!(!allow_client(&rules, "10.0.0.1".parse().unwrap()));
+        assert!(allow_client(&rules, "::1".parse().unwrap()));
+
+        let loopback = loopback_rules();
+        assert!(allow_client(&loopback, "127.0.0.1".parse().unwrap()));
+        assert!(allow_client(&loopback, "::1".parse().unwrap()));
+        assert!(!allow_client(&loopback, "192.168.10.12".parse().unwrap()));
+    }
+}
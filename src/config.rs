@@ -0,0 +1,286 @@
+use dirs::config_dir;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::path::PathBuf;
+
+// path of the configuration file, used if --config is not given
+pub const CONFIG_ENV: &str = "XODO_LINKER_CONFIG";
+// XODO_LINKER__SERVER__PORT=8080 sets server.port
+pub const OVERRIDE_ENV_PREFIX: &str = "XODO_LINKER__";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Cli,
+}
+
+// the configuration file and the overrides that are layered on top of it, in this order
+#[derive(Debug, Default)]
+pub struct Layers {
+    pub file: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+    pub cli: Vec<(String, String)>,
+}
+
+// which layer set each value, keyed by paths like "server.port"
+#[derive(Debug, Default)]
+pub struct Sources(BTreeMap<String, Source>);
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "file {}", path.display()),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Cli => write!(f, "--set"),
+        }
+    }
+}
+
+// --config, then $XODO_LINKER_CONFIG, then the user's config directory, then next to the executable.
+// the first two are used even if they do not exist, so a typo is not silently ignored
+pub fn find_config(explicit: Option<&str>) -> Option<PathBuf> {
+    if let Some(path) = explicit {
+        return Some(PathBuf::from(path));
+    }
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let user_config = config_dir().map(|dir| dir.join("xodo-linker").join("config.yaml"));
+    let next_to_exe = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("config.yaml")));
+    [user_config, next_to_exe]
+        .into_iter()
+        .flatten()
+        .find(|path| path.is_file())
+}
+
+// "8080" is read as a number, "[a, b]" as a list and anything that is not valid yaml as a string
+fn parse_value(raw: &str) -> Value {
+    serde_yaml::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+fn set_path(root: &mut Value, path: &[&str], value: Value) {
+    let mut node = root;
+    for key in path {
+        if !node.is_mapping() {
+            *node = Value::Mapping(Mapping::new());
+        }
+        node = node
+            .as_mapping_mut()
+            .expect("expected node to be a mapping")
+            .entry(Value::String(key.to_string()))
+            .or_insert(Value::Null);
+    }
+    *node = value;
+}
+
+// every mapping key below value, sequences count as a single value
+fn leaves(value: &Value, prefix: &str, output: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Mapping(mapping) if !mapping.is_empty() => {
+            for (key, value) in mapping {
+                let key = match key {
+                    Value::String(key) => key.clone(),
+                    key => flow(key),
+                };
+                let path = match prefix {
+                    "" => key,
+                    prefix => format!("{}.{}", prefix, key),
+                };
+                leaves(value, &path, output);
+            }
+        }
+        value => output.push((prefix.to_string(), value.clone())),
+    }
+}
+
+// a value on a single line, like [allow: ".*\.pdf"]
+fn flow(value: &Value) -> String {
+    match value {
+        Value::Sequence(items) => format!(
+            "[{}]",
+            items.iter().map(flow).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Mapping(mapping) => format!(
+            "{{{}}}",
+            mapping
+                .iter()
+                .map(|(key, value)| format!("{}: {}", flow(key), flow(value)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Tagged(tagged) => format!("{} {}", tagged.tag, flow(&tagged.value)),
+        value => serde_yaml::to_string(value)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+impl Layers {
+    pub fn discover(explicit: Option<&str>, cli: Vec<(String, String)>) -> Layers {
+        let env = env::vars()
+            .filter(|(name, _)| name.starts_with(OVERRIDE_ENV_PREFIX))
+            .collect();
+        Layers {
+            file: find_config(explicit),
+            env,
+            cli,
+        }
+    }
+
+    fn overrides(&self) -> Vec<(Vec<String>, Value, Source)> {
+        let env = self.env.iter().map(|(name, value)| {
+            let path = name[OVERRIDE_ENV_PREFIX.len()..]
+                .split("__")
+                .map(str::to_lowercase)
+                .collect();
+            (path, parse_value(value), Source::Env(name.clone()))
+        });
+        let cli = self.cli.iter().map(|(key, value)| {
+            let path = key.split('.').map(str::to_string).collect();
+            (path, parse_value(value), Source::Cli)
+        });
+        env.chain(cli).collect()
+    }
+
+    // the configuration file with all overrides applied
+    pub fn merge(&self) -> Result<(Value, Sources), String> {
+        let mut sources = Sources::default();
+        let mut root = match &self.file {
+            Some(path) => {
+                let reader = File::open(path)
+                    .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+                let root: Value = serde_yaml::from_reader(reader)
+                    .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+                let mut found = vec![];
+                leaves(&root, "", &mut found);
+                for (key, _) in found {
+                    sources.0.insert(key, Source::File(path.clone()));
+                }
+                root
+            }
+            None => Value::Null,
+        };
+        if !root.is_mapping() {
+            root = Value::Mapping(Mapping::new());
+        }
+        for (path, value, source) in self.overrides() {
+            let path: Vec<&str> = path.iter().map(String::as_str).collect();
+            if path.iter().any(|key| key.is_empty()) {
+                return Err(format!(
+                    "invalid override {} for {}",
+                    source,
+                    path.join(".")
+                ));
+            }
+            let key = path.join(".");
+            // a more specific value of an earlier layer is replaced as well
+            sources
+                .0
+                .retain(|k, _| k != &key && !k.starts_with(&format!("{}.", key)));
+            sources.0.insert(key, source);
+            set_path(&mut root, &path, value);
+        }
+        Ok((root, sources))
+    }
+}
+
+impl Sources {
+    // the source of the closest key that was set explicitly, e.g. "security.rules" for
+    // "security.rules.0" if the whole list came from the file
+    pub fn source_of(&self, key: &str) -> Source {
+        let mut key = key;
+        loop {
+            if let Some(source) = self.0.get(key) {
+                return source.clone();
+            }
+            match key.rsplit_once('.') {
+                Some((parent, _)) => key = parent,
+                None => return Source::Default,
+            }
+        }
+    }
+
+    // "server.port: 8080  # env XODO_LINKER__SERVER__PORT", one line per value
+    pub fn describe(&self, config: &Value) -> String {
+        let mut found = vec![];
+        leaves(config, "", &mut found);
+        found
+            .iter()
+            .map(|(key, value)| format!("{}: {}  # {}\n", key, flow(value), self.source_of(key)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Layers, Source};
+    use std::fs::{remove_file, write};
+
+    fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn layers_overrides_on_file() {
+        let path =
+            std::env::temp_dir().join(format!("xodo-linker-{}-layers.yaml", std::process::id()));
+        write(
+            &path,
+            "server:\n    port: 80\n    addr: 0.0.0.0\nsystem:\n    base_path: /srv\n",
+        )
+        .unwrap();
+        let layers = Layers {
+            file: Some(path.clone()),
+            env: pairs(&[
+                ("XODO_LINKER__SERVER__PORT", "8080"),
+                ("XODO_LINKER__SERVER__CLOSE_TAB", "false"),
+            ]),
+            cli: pairs(&[
+                ("server.port", "9090"),
+                ("security.clients", "[allow: 127.0.0.1]"),
+            ]),
+        };
+        let (config, sources) = layers.merge().unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(config["server"]["port"], 9090);
+        assert_eq!(config["server"]["close_tab"], false);
+        assert_eq!(config["server"]["addr"], "0.0.0.0");
+        assert_eq!(config["security"]["clients"][0]["allow"], "127.0.0.1");
+        assert_eq!(sources.source_of("server.port"), Source::Cli);
+        assert_eq!(
+            sources.source_of("server.close_tab"),
+            Source::Env("XODO_LINKER__SERVER__CLOSE_TAB".to_string())
+        );
+        assert_eq!(sources.source_of("system.base_path"), Source::File(path));
+        assert_eq!(sources.source_of("security.clients.0"), Source::Cli);
+        assert_eq!(sources.source_of("server.workers"), Source::Default);
+        assert!(sources
+            .describe(&config)
+            .contains("security.clients: [{allow: 127.0.0.1}]  # --set\n"));
+    }
+
+    #[test]
+    fn rejects_missing_files() {
+        let layers = Layers {
+            file: Some("/does/not/exist.yaml".into()),
+            ..Layers::default()
+        };
+        assert!(layers.merge().is_err());
+        let invalid = Layers {
+            cli: pairs(&[("server..port", "1")]),
+            ..Layers::default()
+        };
+        assert!(invalid.merge().is_err());
+    }
+}
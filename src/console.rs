@@ -0,0 +1,23 @@
+// the binary uses the windows subsystem so serve runs without a console window. the other
+// commands print to the console of the shell that started them instead
+
+#[cfg(windows)]
+const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+#[cfg(windows)]
+extern "system" {
+    fn AttachConsole(process_id: u32) -> i32;
+}
+
+// does nothing if there is no parent console, e.g. when started from the explorer
+#[cfg(windows)]
+pub fn attach_parent_console() {
+    // SAFETY: AttachConsole has no preconditions, a failure only means there is no console
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+// a console program already writes to the console it was started from
+#[cfg(not(windows))]
+pub fn attach_parent_console() {}
@@ -0,0 +1,139 @@
+use std::fmt;
+
+// everything that can go wrong between receiving a request and launching the file.
+// the Display output is for the logs, message() is shown in the browser tab
+#[derive(Debug, PartialEq)]
+pub enum LinkerError {
+    BadRequest(String),
+    // the method that was used, files are only opened with GET
+    MethodNotAllowed(String),
+    Forbidden(String),
+    HostNotAllowed(String),
+    OutsideRoot(String),
+    SymlinkDenied(String),
+    NotFound(String),
+    NoApplication(String),
+    // the launchers take the path as a string
+    InvalidPath(String),
+    RateLimited,
+    LaunchFailed { status: Option<i32>, stderr: String },
+    ConfigError(String),
+}
+
+impl LinkerError {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            LinkerError::BadRequest(_) => 400,
+            LinkerError::Forbidden(_)
+            | LinkerError::OutsideRoot(_)
+            | LinkerError::SymlinkDenied(_) => 403,
+            LinkerError::NotFound(_) => 404,
+            LinkerError::MethodNotAllowed(_) => 405,
+            LinkerError::NoApplication(_) => 415,
+            LinkerError::HostNotAllowed(_) => 421,
+            LinkerError::RateLimited => 429,
+            LinkerError::ConfigError(_) | LinkerError::InvalidPath(_) => 500,
+            LinkerError::LaunchFailed { .. } => 502,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            LinkerError::BadRequest(err) => format!("could not parse url: {}", err),
+            LinkerError::MethodNotAllowed(method) => format!("{} is not allowed", method),
+            LinkerError::Forbidden(_) => "does not comply".to_string(),
+            LinkerError::HostNotAllowed(host) => format!("{} is not an allowed host", host),
+            LinkerError::OutsideRoot(_) => "not allowed to leave base path".to_string(),
+            LinkerError::SymlinkDenied(_) => "not allowed to open symlinks".to_string(),
+            LinkerError::NotFound(path) => format!("{} does not exist", path),
+            LinkerError::NoApplication(_) => "no application configured for this file".to_string(),
+            LinkerError::InvalidPath(_) => "the path of the file is not valid unicode".to_string(),
+            LinkerError::RateLimited => "too many requests".to_string(),
+            LinkerError::LaunchFailed { status, stderr } => {
+                let status = match status {
+                    Some(code) => format!("exited with status {}", code),
+                    None => "could not be started".to_string(),
+                };
+                match stderr.trim() {
+                    "" => format!("failed to start: the application {}", status),
+                    stderr => format!("failed to start: the application {}. {}", status, stderr),
+                }
+            }
+            LinkerError::ConfigError(_) => "failed to start. check the configuration".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for LinkerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkerError::BadRequest(err) => write!(f, "bad request: {}", err),
+            LinkerError::MethodNotAllowed(method) => write!(f, "method {} is not allowed", method),
+            LinkerError::Forbidden(path) => write!(f, "request for {} was not allowed", path),
+            LinkerError::HostNotAllowed(host) => {
+                write!(f, "host {:?} is not in security.allowed_hosts", host)
+            }
+            LinkerError::OutsideRoot(path) => write!(f, "{} is outside of base_path", path),
+            LinkerError::SymlinkDenied(path) => write!(f, "{} is a symlink", path),
+            LinkerError::NotFound(path) => write!(f, "{} does not exist", path),
+            LinkerError::NoApplication(path) => {
+                write!(f, "no application configured for {}", path)
+            }
+            LinkerError::InvalidPath(path) => {
+                write!(f, "the path of {} is not valid unicode", path)
+            }
+            LinkerError::RateLimited => write!(f, "too many requests"),
+            LinkerError::LaunchFailed { status, stderr } => {
+                write!(
+                    f,
+                    "process failed with status {:?}. stderr: {:?}",
+                    status, stderr
+                )
+            }
+            LinkerError::ConfigError(err) => write!(f, "configuration error: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LinkerError;
+
+    #[test]
+    fn describes_launch_failures() {
+        let failed = LinkerError::LaunchFailed {
+            status: Some(1),
+            stderr: "cannot open display\n".to_string(),
+        };
+        assert_eq!(failed.status_code(), 502);
+        assert_eq!(
+            failed.message(),
+            "failed to start: the application exited with status 1. cannot open display"
+        );
+        let missing = LinkerError::LaunchFailed {
+            status: None,
+            stderr: String::new(),
+        };
+        assert_eq!(
+            missing.message(),
+            "failed to start: the application could not be started"
+        );
+    }
+
+    #[test]
+    fn maps_status_codes() {
+        assert_eq!(
+            LinkerError::Forbidden("/x.pdf".to_string()).status_code(),
+            403
+        );
+        assert_eq!(
+            LinkerError::MethodNotAllowed("POST".to_string()).status_code(),
+            405
+        );
+        // nothing was launched, so it is not a launch failure
+        assert_eq!(
+            LinkerError::InvalidPath("/x.pdf".to_string()).status_code(),
+            500
+        );
+    }
+}
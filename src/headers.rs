@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Request};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderPolicy {
+    // origins like https://www.notion.so, taken from the Origin or Referer header. empty allows any
+    #[serde(default)]
+    allowed_origins: Vec<String>,
+    // reject requests that carry neither Origin nor Referer (e.g. typed into the address bar).
+    // defaults to true if allowed_origins is set, otherwise rel="noreferrer" gets around it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    require_origin: Option<bool>,
+    // Sec-Fetch-Dest values that are rejected on top of everything that is not a top-level
+    // navigation, e.g. document if files should only be opened by tools like curl
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deny_fetch_dest: Vec<String>,
+}
+
+fn header<'a>(headers: &'a [Header], name: &'static str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+// "https://www.notion.so/page?x" -> "https://www.notion.so"
+fn origin_of(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    format!("{}://{}", scheme, host).to_lowercase()
+}
+
+impl HeaderPolicy {
+    fn allow_origin(&self, headers: &[Header]) -> bool {
+        // the redirect page that passes #page on, its own request was checked already
+        if header(headers, "Sec-Fetch-Site") == Some("same-origin") {
+            return true;
+        }
+        let origin = match header(headers, "Origin")
+            .filter(|origin| *origin != "null")
+            .or_else(|| header(headers, "Referer"))
+        {
+            Some(origin) => origin_of(origin),
+            None => {
+                return !self
+                    .require_origin
+                    .unwrap_or(!self.allowed_origins.is_empty())
+            }
+        };
+        if self.allowed_origins.is_empty()
+            || self
+                .allowed_origins
+                .iter()
+                .any(|allowed| origin_of(allowed) == origin)
+        {
+            true
+        } else {
+            println!("origin {} is not allowed", origin);
+            false
+        }
+    }
+
+    // only top-level navigations open files. fetch(), <img> or <audio> from any site would
+    // otherwise launch them too. clients without Sec-Fetch-* headers (curl, old browsers) pass
+    fn allow_fetch_dest(&self, headers: &[Header]) -> bool {
+        let mode = header(headers, "Sec-Fetch-Mode");
+        let dest = header(headers, "Sec-Fetch-Dest");
+        if (mode.is_some() || dest.is_some())
+            && (mode != Some("navigate") || dest != Some("document"))
+        {
+            println!(
+                "only top-level navigations are allowed, not Sec-Fetch-Mode {:?} with Sec-Fetch-Dest {:?}",
+                mode, dest
+            );
+            return false;
+        }
+        match dest {
+            Some(dest)
+                if self
+                    .deny_fetch_dest
+                    .iter()
+                    .any(|d| d.eq_ignore_ascii_case(dest)) =>
+            {
+                println!("requests for Sec-Fetch-Dest {} are not allowed", dest);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    pub fn allow_headers(&self, headers: &[Header]) -> bool {
+        self.allow_origin(headers) && self.allow_fetch_dest(headers)
+    }
+
+    pub fn allow_request(&self, request: &Request) -> bool {
+        self.allow_headers(request.headers())
+    }
+}
+
+// true if another site sent the request, e.g. a form that posts to /__shutdown.
+// browsers send Origin with every POST from a page, curl and typed urls send neither header
+pub fn is_cross_site(headers: &[Header]) -> bool {
+    let site = header(headers, "Sec-Fetch-Site");
+    header(headers, "Origin").is_some()
+        || site.is_some_and(|site| !["none", "same-origin"].contains(&site))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_cross_site, HeaderPolicy};
+    use tiny_http::Header;
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<Header> {
+        headers
+            .iter()
+            .map(|(field, value)| Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn checks_origin_and_referer() {
+        let policy: HeaderPolicy =
+            serde_yaml::from_str("allowed_origins: [\"https://www.notion.so\"]").unwrap();
+        assert!(policy.allow_headers(&headers(&[("Origin", "https://www.notion.so")])));
+        assert!(policy.allow_headers(&headers(&[("Referer", "https://www.Notion.so/page?p=1")])));
+        assert!(!policy.allow_headers(&headers(&[("Referer", "https://evil.example/")])));
+        assert!(!policy.allow_headers(&headers(&[(
+            "Origin",
+            "https://www.notion.so.evil.example"
+        )])));
+        // a link with rel="noreferrer" sends neither header
+        assert!(!policy.allow_headers(&headers(&[])));
+        assert!(HeaderPolicy::default().allow_headers(&headers(&[])));
+
+        let lenient: HeaderPolicy = serde_yaml::from_str(
+            "allowed_origins: [\"https://www.notion.so\"]\nrequire_origin: false",
+        )
+        .unwrap();
+        assert!(lenient.allow_headers(&headers(&[])));
+        assert!(!lenient.allow_headers(&headers(&[("Referer", "https://evil.example/")])));
+
+        // browsers set Sec-Fetch-Site, other sites can not claim to be same-origin
+        assert!(policy.allow_headers(&headers(&[
+            ("Sec-Fetch-Site", "same-origin"),
+            ("Referer", "http://localhost/x.pdf")
+        ])));
+
+        let strict: HeaderPolicy = serde_yaml::from_str(
+            "allowed_origins: [\"https://www.notion.so/\"]\nrequire_origin: true",
+        )
+        .unwrap();
+        assert!(!strict.allow_headers(&headers(&[])));
+        assert!(strict.allow_headers(&headers(&[("Referer", "https://www.notion.so/x")])));
+    }
+
+    #[test]
+    fn rejects_embedded_requests() {
+        let policy = HeaderPolicy::default();
+        assert!(!policy.allow_headers(&headers(&[("Sec-Fetch-Dest", "image")])));
+        assert!(!policy.allow_headers(&headers(&[("Sec-Fetch-Dest", "iframe")])));
+        assert!(policy.allow_headers(&headers(&[
+            ("Sec-Fetch-Mode", "navigate"),
+            ("Sec-Fetch-Dest", "document")
+        ])));
+        // fetch("http://localhost/x.pdf", {mode: "no-cors"}) from any site
+        assert!(!policy.allow_headers(&headers(&[
+            ("Sec-Fetch-Mode", "no-cors"),
+            ("Sec-Fetch-Dest", "empty")
+        ])));
+        assert!(!policy.allow_headers(&headers(&[
+            ("Sec-Fetch-Mode", "navigate"),
+            ("Sec-Fetch-Dest", "iframe")
+        ])));
+        assert!(!policy.allow_headers(&headers(&[("Sec-Fetch-Dest", "audio")])));
+
+        let strict: HeaderPolicy = serde_yaml::from_str("deny_fetch_dest: [document]").unwrap();
+        assert!(!strict.allow_headers(&headers(&[
+            ("Sec-Fetch-Mode", "navigate"),
+            ("Sec-Fetch-Dest", "document")
+        ])));
+        assert!(policy.allow_headers(&headers(&[("Origin", "https://evil.example")])));
+    }
+
+    #[test]
+    fn detects_cross_site_requests() {
+        assert!(!is_cross_site(&headers(&[])));
+        assert!(!is_cross_site(&headers(&[("Sec-Fetch-Site", "none")])));
+        assert!(is_cross_site(&headers(&[(
+            "Origin",
+            "https://evil.example"
+        )])));
+        assert!(is_cross_site(&headers(&[("Sec-Fetch-Site", "cross-site")])));
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs::{copy, read_to_string, write};
+use std::path::Path;
+
+// the lines between these markers belong to xodo-linker, everything else is left alone
+const BEGIN: &str = "# BEGIN xodo-linker";
+const END: &str = "# END xodo-linker";
+
+pub fn default_hosts_file() -> &'static str {
+    if cfg!(target_os = "windows") {
+        r"C:\Windows\System32\drivers\etc\hosts"
+    } else {
+        "/etc/hosts"
+    }
+}
+
+// hosts file contents without our block
+pub fn remove_block(contents: &str) -> String {
+    let mut output = String::with_capacity(contents.len());
+    let mut inside = false;
+    for line in contents.split_inclusive('\n') {
+        match line.trim() {
+            BEGIN => inside = true,
+            END if inside => inside = false,
+            _ if inside => {}
+            _ => output.push_str(line),
+        }
+    }
+    output
+}
+
+// hosts file contents with a block that points hostname to the loopback addresses
+pub fn add_block(contents: &str, hostname: &str) -> String {
+    let mut output = remove_block(contents);
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str(&format!(
+        "{}\n127.0.0.1 {}\n::1 {}\n{}\n",
+        BEGIN, hostname, hostname, END
+    ));
+    output
+}
+
+// writes the new contents if they differ. the first change copies the original file to
+// <path>.bak, later ones keep it. false if nothing had to be changed
+fn update(path: &Path, change: impl Fn(&str) -> String) -> Result<bool, String> {
+    let contents =
+        read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let changed = change(&contents);
+    if changed == contents {
+        return Ok(false);
+    }
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    if !Path::new(&backup).exists() {
+        copy(path, &backup).map_err(|e| format!("could not back up {}: {}", path.display(), e))?;
+    }
+    write(path, changed).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    Ok(true)
+}
+
+pub fn install(path: &Path, hostname: &str) -> Result<bool, String> {
+    update(path, |contents| add_block(contents, hostname))
+}
+
+pub fn uninstall(path: &Path) -> Result<bool, String> {
+    update(path, remove_block)
+}
+
+// "xodo", "XODO:8080" and "xodo." all match the hostname xodo
+pub fn matches_host(host: &str, hostname: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    let hostname = hostname.trim_start_matches('[').trim_end_matches(']');
+    name.trim_end_matches('.').eq_ignore_ascii_case(hostname)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{add_block, install, matches_host, remove_block, uninstall};
+    use std::fs::{read_to_string, remove_file, write};
+
+    #[test]
+    fn manages_block() {
+        let hosts = "127.0.0.1 localhost\n# comment";
+        let installed = add_block(hosts, "xodo");
+        assert_eq!(
+            installed,
+            "127.0.0.1 localhost\n# comment\n# BEGIN xodo-linker\n127.0.0.1 xodo\n::1 xodo\n# END xodo-linker\n"
+        );
+        assert_eq!(add_block(&installed, "xodo"), installed);
+        assert_eq!(remove_block(&installed), "127.0.0.1 localhost\n# comment\n");
+        assert_eq!(remove_block(hosts), hosts);
+    }
+
+    #[test]
+    fn updates_hosts_file() {
+        let path = std::env::temp_dir().join(format!("xodo-linker-{}-hosts", std::process::id()));
+        let backup = path.with_extension("bak");
+        let _ = remove_file(&backup);
+        write(&path, "127.0.0.1 localhost\n").unwrap();
+
+        assert_eq!(install(&path, "xodo"), Ok(true));
+        assert!(read_to_string(&path).unwrap().contains("127.0.0.1 xodo\n"));
+        assert_eq!(read_to_string(&backup).unwrap(), "127.0.0.1 localhost\n");
+        assert_eq!(install(&path, "xodo"), Ok(false));
+
+        assert_eq!(uninstall(&path), Ok(true));
+        assert_eq!(read_to_string(&path).unwrap(), "127.0.0.1 localhost\n");
+        assert_eq!(uninstall(&path), Ok(false));
+        // the backup still holds the original file
+        assert_eq!(read_to_string(&backup).unwrap(), "127.0.0.1 localhost\n");
+
+        remove_file(&path).unwrap();
+        remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn matches_host_header() {
+        assert!(matches_host("xodo", "xodo"));
+        assert!(matches_host("XODO:8080", "xodo"));
+        assert!(matches_host("xodo.", "xodo"));
+        assert!(matches_host("[::1]:80", "::1"));
+        assert!(!matches_host("evil.example", "xodo"));
+        assert!(!matches_host("xodo.evil.example", "xodo"));
+        assert!(!matches_host("", "xodo"));
+    }
+}
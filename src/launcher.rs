@@ -0,0 +1,332 @@
+use crate::error::LinkerError;
+use crate::template::{expand, expand_all, LAUNCH_PLACEHOLDERS};
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+use std::thread;
+
+pub trait Launcher {
+    fn command(&self, path_to_file: &str, page: Option<u32>) -> Command;
+
+    // fire and forget, the exit status is only logged. with wait a failing exit status is an error
+    fn launch(&self, path_to_file: &str, page: Option<u32>, wait: bool) -> Result<(), LinkerError> {
+        let command = self.command(path_to_file, page);
+        println!("opening file {} with {:?}", path_to_file, command);
+        run_command(command, wait)
+    }
+}
+
+// powershell.exe -command "openwith 'path\to\file\with\backslashes.pdf'"
+pub struct OpenWithLauncher;
+
+// xdg-open path/to/file.pdf or gio open path/to/file.pdf
+pub struct XdgOpenLauncher {
+    use_gio: bool,
+}
+
+// any program, "{{path}}" in args is replaced by the absolute path of the file.
+// page_args are used instead of args if a page was requested, "{{page}}" is replaced by it
+pub struct CustomCommandLauncher {
+    program: String,
+    args: Vec<String>,
+    page_args: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum LauncherConfig {
+    #[default]
+    Auto,
+    OpenWith,
+    XdgOpen,
+    GioOpen,
+    Custom {
+        program: String,
+        #[serde(default = "default_args")]
+        args: Vec<String>,
+        #[serde(default)]
+        page_args: Vec<String>,
+    },
+}
+
+fn default_args() -> Vec<String> {
+    vec!["{{path}}".to_string()]
+}
+
+impl LauncherConfig {
+    pub fn expand_placeholders(&mut self) -> Result<(), String> {
+        if let LauncherConfig::Custom {
+            program,
+            args,
+            page_args,
+        } = self
+        {
+            *program = expand(program, &[])?;
+            expand_all(args, LAUNCH_PLACEHOLDERS)?;
+            expand_all(page_args, LAUNCH_PLACEHOLDERS)?;
+        }
+        Ok(())
+    }
+
+    pub fn build(&self) -> Box<dyn Launcher> {
+        match self {
+            LauncherConfig::Auto => {
+                if cfg!(target_os = "windows") {
+                    Box::new(OpenWithLauncher)
+                } else if cfg!(target_os = "macos") {
+                    Box::new(CustomCommandLauncher::new("open", default_args(), vec![]))
+                } else {
+                    Box::new(XdgOpenLauncher { use_gio: false })
+                }
+            }
+            LauncherConfig::OpenWith => Box::new(OpenWithLauncher),
+            LauncherConfig::XdgOpen => Box::new(XdgOpenLauncher { use_gio: false }),
+            LauncherConfig::GioOpen => Box::new(XdgOpenLauncher { use_gio: true }),
+            LauncherConfig::Custom {
+                program,
+                args,
+                page_args,
+            } => Box::new(CustomCommandLauncher::new(
+                program,
+                args.clone(),
+                page_args.clone(),
+            )),
+        }
+    }
+}
+
+fn run_command(mut command: Command, wait: bool) -> Result<(), LinkerError> {
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| LinkerError::LaunchFailed {
+            status: None,
+            stderr: e.to_string(),
+        })?;
+    let wait_for_exit = move || {
+        let output = child
+            .wait_with_output()
+            .map_err(|e| LinkerError::LaunchFailed {
+                status: None,
+                stderr: e.to_string(),
+            })?;
+        if output.status.success() {
+            println!("{:?}", output);
+            Ok(())
+        } else {
+            Err(LinkerError::LaunchFailed {
+                status: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            })
+        }
+    };
+
+    if wait {
+        wait_for_exit()
+    } else {
+        // still wait in the background, so the exit status is logged and no zombie is left behind
+        thread::spawn(move || {
+            if let Err(err) = wait_for_exit() {
+                println!("{}", err);
+            }
+        });
+        Ok(())
+    }
+}
+
+fn ignore_page(page: Option<u32>) {
+    if let Some(page) = page {
+        println!("launcher does not support pages, ignoring page {}", page);
+    }
+}
+
+// a single-quoted powershell string, nothing in it is expanded. powershell also reads the
+// typographic quotes as single quotes, so they are doubled as well
+fn powershell_literal(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('\'');
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            literal.push(c);
+        }
+        literal.push(c);
+    }
+    literal.push('\'');
+    literal
+}
+
+impl Launcher for OpenWithLauncher {
+    fn command(&self, path_to_file: &str, page: Option<u32>) -> Command {
+        ignore_page(page);
+        let mut command = Command::new("powershell");
+        command.args([
+            "-command",
+            &format!("openwith {}", powershell_literal(path_to_file)),
+        ]);
+        command
+    }
+}
+
+impl Launcher for XdgOpenLauncher {
+    fn command(&self, path_to_file: &str, page: Option<u32>) -> Command {
+        ignore_page(page);
+        if self.use_gio {
+            let mut command = Command::new("gio");
+            command.args(["open", path_to_file]);
+            command
+        } else {
+            let mut command = Command::new("xdg-open");
+            command.arg(path_to_file);
+            command
+        }
+    }
+}
+
+impl CustomCommandLauncher {
+    pub fn new(program: &str, args: Vec<String>, page_args: Vec<String>) -> Self {
+        CustomCommandLauncher {
+            program: program.to_string(),
+            args,
+            page_args,
+        }
+    }
+
+    fn build_args(&self, path_to_file: &str, page: Option<u32>) -> Vec<String> {
+        let template = match page {
+            Some(_) if !self.page_args.is_empty() => &self.page_args,
+            _ => &self.args,
+        };
+        let page = page.unwrap_or(1).to_string();
+        template
+            .iter()
+            .map(|arg| {
+                arg.replace("{{path}}", path_to_file)
+                    .replace("{{page}}", &page)
+            })
+            .collect()
+    }
+}
+
+impl Launcher for CustomCommandLauncher {
+    fn command(&self, path_to_file: &str, page: Option<u32>) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(self.build_args(path_to_file, page));
+        command
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{powershell_literal, CustomCommandLauncher, LauncherConfig};
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn custom_command_replaces_path() {
+        let launcher = CustomCommandLauncher::new("xodo", strings(&["--open", "{{path}}"]), vec![]);
+        assert_eq!(
+            launcher.build_args("/tmp/test.pdf", None),
+            strings(&["--open", "/tmp/test.pdf"])
+        );
+        // no page_args configured, fall back to plain opening
+        assert_eq!(
+            launcher.build_args("/tmp/test.pdf", Some(14)),
+            strings(&["--open", "/tmp/test.pdf"])
+        );
+    }
+
+    #[test]
+    fn custom_command_uses_page_args() {
+        let launcher = CustomCommandLauncher::new(
+            "okular",
+            strings(&["{{path}}"]),
+            strings(&["--page", "{{page}}", "{{path}}"]),
+        );
+        assert_eq!(
+            launcher.build_args("/tmp/test.pdf", Some(14)),
+            strings(&["--page", "14", "/tmp/test.pdf"])
+        );
+        assert_eq!(
+            launcher.build_args("/tmp/test.pdf", None),
+            strings(&["/tmp/test.pdf"])
+        );
+    }
+
+    #[test]
+    fn quotes_powershell_paths() {
+        assert_eq!(
+            powershell_literal(r"C:\x$(calc)`.pdf"),
+            r"'C:\x$(calc)`.pdf'"
+        );
+        assert_eq!(
+            powershell_literal("it's \u{2019}x\u{2019}.pdf"),
+            "'it''s \u{2019}\u{2019}x\u{2019}\u{2019}.pdf'"
+        );
+    }
+
+    #[test]
+    fn parses_launcher_config() {
+        let cfg: LauncherConfig = serde_yaml::from_str("type: custom\nprogram: okular").unwrap();
+        assert_eq!(
+            cfg,
+            LauncherConfig::Custom {
+                program: "okular".to_string(),
+                args: vec!["{{path}}".to_string()],
+                page_args: vec![]
+            }
+        );
+        assert!(
+            serde_yaml::from_str::<LauncherConfig>("type: custom\nprogram: x\nargz: []").is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_failed_launches() {
+        use super::Launcher;
+        use crate::error::LinkerError;
+
+        let failing =
+            CustomCommandLauncher::new("sh", strings(&["-c", "echo oops >&2; exit 3"]), vec![]);
+        assert_eq!(
+            failing.launch("/tmp/test.pdf", None, true),
+            Err(LinkerError::LaunchFailed {
+                status: Some(3),
+                stderr: "oops\n".to_string()
+            })
+        );
+        let missing = CustomCommandLauncher::new("xodo-linker-does-not-exist", vec![], vec![]);
+        assert!(matches!(
+            missing.launch("/tmp/test.pdf", None, true),
+            Err(LinkerError::LaunchFailed { status: None, .. })
+        ));
+        // without waiting only a program that can not be started is an error
+        assert_eq!(failing.launch("/tmp/test.pdf", None, false), Ok(()));
+        assert!(missing.launch("/tmp/test.pdf", None, false).is_err());
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn opens_dialog() {
+        use super::{Launcher, OpenWithLauncher};
+        OpenWithLauncher
+            .launch(r"C:\Users\tim\Downloads\test.pdf", None, true)
+            .unwrap()
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn opens_dialog_onedrive() {
+        use super::{Launcher, OpenWithLauncher};
+        OpenWithLauncher
+            .launch(
+                r"C:\Users\tim\OneDrive\OneDrive - epfl.ch\test.pdf",
+                None,
+                true,
+            )
+            .unwrap()
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// token bucket per client: up to burst requests at once, refilled with per_second tokens
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    burst: u32,
+    per_second: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// remembers when a file was launched last, so double clicks and retries open it only once
+#[derive(Debug, Default)]
+pub struct Debouncer {
+    launches: Mutex<HashMap<String, Instant>>,
+}
+
+// what the limits remember between requests. it lives in the service, so a reloaded
+// configuration keeps the buckets and launches of the previous one
+#[derive(Debug, Default)]
+pub struct Limits {
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    pub debouncer: Debouncer,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit::new(10, 1.0)
+    }
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        RateLimit { burst, per_second }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.burst == 0 || self.per_second.is_nan() || self.per_second <= 0.0 {
+            Err("rate_limit needs a burst of at least 1 and a positive per_second".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Limits {
+    pub fn check_rate(&self, limit: &RateLimit, ip: IpAddr) -> bool {
+        self.check_rate_at(limit, ip, Instant::now())
+    }
+
+    fn check_rate_at(&self, limit: &RateLimit, ip: IpAddr, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let burst = limit.burst as f64;
+        // forget clients whose bucket is full again
+        buckets.retain(|_, bucket| {
+            let refill =
+                now.saturating_duration_since(bucket.updated).as_secs_f64() * limit.per_second;
+            bucket.tokens + refill < burst
+        });
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let refill = now.saturating_duration_since(bucket.updated).as_secs_f64() * limit.per_second;
+        bucket.tokens = (bucket.tokens + refill).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Debouncer {
+    // true if the file was not launched within the window
+    pub fn should_launch(&self, path: &str, window: Duration) -> bool {
+        self.should_launch_at(path, window, Instant::now())
+    }
+
+    fn should_launch_at(&self, path: &str, window: Duration, now: Instant) -> bool {
+        let mut launches = self.launches.lock().unwrap_or_else(|e| e.into_inner());
+        launches.retain(|_, launched| now.saturating_duration_since(*launched) < window);
+        if launches.contains_key(path) {
+            false
+        } else {
+            launches.insert(path.to_string(), now);
+            true
+        }
+    }
+
+    // the launch failed, so a retry should launch again
+    pub fn forget(&self, path: &str) {
+        let mut launches = self.launches.lock().unwrap_or_else(|e| e.into_inner());
+        launches.remove(path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Debouncer, Limits, RateLimit};
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn limits_each_client() {
+        let limit = RateLimit::new(2, 1.0);
+        let limits = Limits::default();
+        let start = Instant::now();
+        let client: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "::1".parse().unwrap();
+
+        assert!(limits.check_rate_at(&limit, client, start));
+        assert!(limits.check_rate_at(&limit, client, start));
+        assert!(!limits.check_rate_at(&limit, client, start));
+        assert!(limits.check_rate_at(&limit, other, start));
+        assert!(!limits.check_rate_at(&limit, client, start + Duration::from_millis(500)));
+        assert!(limits.check_rate_at(&limit, client, start + Duration::from_millis(1500)));
+        assert!(!limits.check_rate_at(&limit, client, start + Duration::from_millis(1600)));
+        // a reloaded configuration keeps the buckets
+        let reloaded = RateLimit::new(2, 1.0);
+        assert!(!limits.check_rate_at(&reloaded, client, start + Duration::from_millis(1600)));
+    }
+
+    #[test]
+    fn debounces_launches() {
+        let debouncer = Debouncer::default();
+        let window = Duration::from_secs(2);
+        let start = Instant::now();
+
+        assert!(debouncer.should_launch_at("/a.pdf", window, start));
+        assert!(!debouncer.should_launch_at("/a.pdf", window, start + Duration::from_secs(1)));
+        assert!(debouncer.should_launch_at("/b.pdf", window, start + Duration::from_secs(1)));
+        assert!(debouncer.should_launch_at("/a.pdf", window, start + Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn retries_failed_launches() {
+        let debouncer = Debouncer::default();
+        let window = Duration::from_secs(2);
+        let start = Instant::now();
+
+        assert!(debouncer.should_launch_at("/a.pdf", window, start));
+        debouncer.forget("/a.pdf");
+        assert!(debouncer.should_launch_at("/a.pdf", window, start + Duration::from_secs(1)));
+        assert!(!debouncer.should_launch_at("/a.pdf", window, start + Duration::from_secs(1)));
+    }
+}
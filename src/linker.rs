@@ -1,212 +1,777 @@
-use dirs::home_dir;
-use regex::Regex;
+use crate::apps::{default_apps, find_app, AppConfig};
+use crate::clients::{allow_client, loopback_rules, ClientRule};
+use crate::config::Layers;
+use crate::error::LinkerError;
+use crate::headers::{is_cross_site, HeaderPolicy};
+use crate::hosts::{self, default_hosts_file, matches_host};
+use crate::launcher::{Launcher, LauncherConfig};
+use crate::limits::{Limits, RateLimit};
+use crate::listen::ListenAddr;
+use crate::request::{percent_decode, percent_encode, RequestedFile};
+use crate::responses::{fragment_redirect, ResponseTemplates};
+use crate::rules::{unanchored, AccessRules, Action, Decision, Pattern, Rule};
+use crate::template::expand;
+use crate::tls::TlsConfig;
+use crate::unicode::nfc;
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
-use serde_yaml::from_reader;
-use std::error::Error;
-use std::fs::{canonicalize, File};
-use std::path::Path;
-use std::process::Command;
-use substring::Substring;
-use tiny_http::{Header, Request, Response, Server};
+use serde_yaml::Value;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{canonicalize, read_dir, read_to_string};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// POST to it to stop the server, e.g. curl -X POST http://localhost/__shutdown
+const SHUTDOWN_PATH: &str = "/__shutdown";
 
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Linker {
+    #[serde(default)]
     pub security: SecurityConfig,
+    #[serde(default)]
     pub server: ServerConfig,
+    #[serde(default)]
     pub system: SystemConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct SystemConfig {
-    hostname: String, // TODO
+    // links look like http://<hostname>/file.pdf. install-hostname points it to this machine
+    hostname: String,
+    // the hosts file install-hostname changes, the system's hosts file if not set
+    #[serde(default)]
+    hosts_file: Option<String>,
     base_path: String,
+    #[serde(default)]
+    launcher: LauncherConfig,
+    // wait for the launcher to exit and report a failing exit status to the browser.
+    // otherwise launches are fire and forget and failures only show up in the logs
+    #[serde(default)]
+    wait_for_exit: bool,
+    #[serde(default = "default_apps")]
+    apps: Vec<AppConfig>,
+    #[serde(default)]
+    symlinks: SymlinkPolicy,
+    #[serde(default)]
+    mounts: BTreeMap<String, MountConfig>,
+    // used for urls without a mount prefix. falls back to base_path if not set
+    #[serde(default)]
+    default_mount: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MountConfig {
+    path: String,
+    // override the rules in security if set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_action: Option<Action>,
+    #[serde(
+        default,
+        deserialize_with = "unanchored",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    blacklist: Vec<Pattern>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    whitelist: Vec<Pattern>,
+    // overrides system.launcher if set
+    #[serde(default)]
+    launcher: Option<LauncherConfig>,
 }
 
 impl Default for SystemConfig {
     fn default() -> Self {
         SystemConfig {
             hostname: "xodo".to_string(),
+            hosts_file: None,
             base_path: r"{{home_dir}}\OneDrive\ONEDRI~1".to_string(),
+            launcher: LauncherConfig::default(),
+            wait_for_exit: false,
+            apps: default_apps(),
+            symlinks: SymlinkPolicy::default(),
+            mounts: BTreeMap::new(),
+            default_mount: None,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
+    #[serde(default = "default_port")]
     port: u16,
-
+    #[serde(default = "default_addr")]
     addr: String,
+    // plain http on each of these instead of addr and port, e.g. [127.0.0.1:8080, "unix:/run/xodo.sock"].
+    // requests over a unix socket come from this machine, the client checks do not apply to them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    listen: Vec<ListenAddr>,
+    #[serde(default = "default_close_tab")]
     close_tab: bool,
+    // number of requests that are handled at the same time
+    #[serde(default = "default_workers")]
+    workers: usize,
+    // a file that was opened within this window is not opened again
+    #[serde(default = "default_debounce_ms")]
+    debounce_ms: u64,
+    #[serde(default)]
+    responses: ResponseTemplates,
+    // serve https, needs a build with --features tls
+    #[serde(default)]
+    tls: Option<TlsConfig>,
+}
+
+fn default_port() -> u16 {
+    80
+}
+
+fn default_addr() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_close_tab() -> bool {
+    true
+}
+
+fn default_workers() -> usize {
+    4
+}
+
+fn default_debounce_ms() -> u64 {
+    2000
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         ServerConfig {
-            port: 80,
-            addr: "0.0.0.0".to_string(),
-            close_tab: true,
+            port: default_port(),
+            addr: default_addr(),
+            listen: vec![],
+            close_tab: default_close_tab(),
+            workers: default_workers(),
+            debounce_ms: default_debounce_ms(),
+            responses: ResponseTemplates::default(),
+            tls: None,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct SecurityConfig {
+    // shorthand for clients: [allow: 127.0.0.0/8, allow: ::1/128], ignored if clients is set
+    #[serde(default = "default_force_loopback")]
     force_loopback: bool,
-    #[serde(with = "serde_regex")]
-    blacklist: Vec<Regex>,
-    #[serde(with = "serde_regex")]
-    whitelist: Vec<Regex>,
+    // first match wins, clients that match no rule are denied
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    clients: Vec<ClientRule>,
+    // first match wins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_action: Option<Action>,
+    // old style lists, read as allow rules for the whitelist followed by deny rules for the blacklist
+    #[serde(
+        default,
+        deserialize_with = "unanchored",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    blacklist: Vec<Pattern>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    whitelist: Vec<Pattern>,
+    // if set, links need to be signed: /path.pdf?sig=...&exp=... (see the link command)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    #[serde(default)]
+    headers: HeaderPolicy,
+    // null disables rate limiting
+    #[serde(default = "default_rate_limit")]
+    rate_limit: Option<RateLimit>,
+    // host headers the server answers to, ports are ignored. {{hostname}} is system.hostname.
+    // a site that points its own name to this machine (dns rebinding) is refused
+    #[serde(default = "default_allowed_hosts")]
+    allowed_hosts: Vec<String>,
+}
+
+fn default_force_loopback() -> bool {
+    true
+}
+
+fn default_rate_limit() -> Option<RateLimit> {
+    Some(RateLimit::default())
+}
+
+fn default_allowed_hosts() -> Vec<String> {
+    ["localhost", "127.0.0.1", "[::1]", "{{hostname}}"]
+        .iter()
+        .map(|host| host.to_string())
+        .collect()
 }
 
 impl Default for SecurityConfig {
     fn default() -> Self {
         SecurityConfig {
-            force_loopback: true,
-            blacklist: vec![
-                Regex::new(r"/favicon\.ico").expect("expected preprogrammed regex to be ok")
+            force_loopback: default_force_loopback(),
+            clients: vec![],
+            rules: vec![
+                Rule::Deny(
+                    Pattern::parse(r"favicon\.ico").expect("expected preprogrammed regex to be ok"),
+                ),
+                Rule::Allow(
+                    Pattern::parse(r".*\.pdf").expect("expected preprogrammed regex to be ok"),
+                ),
             ],
-            whitelist: vec![Regex::new(r".*\.pdf").expect("expected preprogrammed regex to be ok")],
+            default_action: Some(Action::Deny),
+            blacklist: vec![],
+            whitelist: vec![],
+            secret: None,
+            headers: HeaderPolicy::default(),
+            rate_limit: default_rate_limit(),
+            allowed_hosts: default_allowed_hosts(),
+        }
+    }
+}
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    Follow,
+    Deny,
+    #[default]
+    WithinRoot,
+}
+
+// the outcome of the resolve command
+pub struct Resolution {
+    pub mount: Option<String>,
+    pub decision: Decision,
+    pub path: Result<String, LinkerError>,
+    // None if the path could not be resolved
+    pub command: Option<Result<String, LinkerError>>,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "mount: {}",
+            self.mount.as_deref().unwrap_or("(base_path)")
+        )?;
+        writeln!(f, "rule: {}", self.decision)?;
+        match &self.path {
+            Ok(path) => writeln!(f, "path: {}", path)?,
+            Err(err) => writeln!(f, "path: {}", err)?,
+        }
+        match &self.command {
+            Some(Ok(command)) => write!(f, "launcher: {}", command),
+            Some(Err(err)) => write!(f, "launcher: {}", err),
+            None => write!(f, "launcher: -"),
         }
     }
 }
+
+impl MountConfig {
+    fn access_rules(&self) -> AccessRules<'_> {
+        AccessRules {
+            rules: &self.rules,
+            default_action: self.default_action,
+            blacklist: &self.blacklist,
+            whitelist: &self.whitelist,
+        }
+    }
+}
+
 impl SystemConfig {
-    pub fn get_absolute_pdf_path(&self, requested_path: &str) -> Result<String, String> {
-        match home_dir() {
-            Some(home) => {
-                println!("getting absolute path for {}", requested_path);
-                // join base_path and requested_path
-                let file_path = Path::new(&self.base_path.replace(
-                    "{{home_dir}}",
-                    home.to_str().ok_or("could not resolve home")?,
-                ))
-                .join(requested_path.substring(1, requested_path.len()));
-                // println!("gotten filepath {:?}", file_path);
-
-                // canonicalize the path
-                let file_path = canonicalize(file_path)
-                    .map_err(|e| format!("Could not canonicalize: {}", e))?;
-                // println!("resolved to  {:?}", file_path);
-
-                // remove prefix
-                let file_path = file_path
-                    .to_str()
-                    .ok_or("could not transform path to string")?
-                    .to_string();
-
-                if file_path.starts_with(r"\\?\") {
-                    println!("removed prefix");
-                    Ok(file_path.replacen(r"\\?\", "", 1))
-                } else {
-                    Ok(file_path)
-                }
+    // "/epfl/course/x.pdf" is looked up as "/course/x.pdf" in the mount "epfl".
+    // urls without a known mount prefix go to default_mount or base_path
+    pub fn find_mount(
+        &self,
+        requested: &RequestedFile,
+    ) -> Result<(Option<&MountConfig>, RequestedFile), LinkerError> {
+        let (prefix, rest) = requested
+            .rel_path
+            .split_once('/')
+            .unwrap_or((&requested.rel_path, ""));
+        if let Some(mount) = self.mounts.get(prefix) {
+            let requested = RequestedFile {
+                rel_path: rest.to_string(),
+                mount: Some(prefix.to_string()),
+                ..requested.clone()
+            };
+            return Ok((Some(mount), requested));
+        }
+        match &self.default_mount {
+            Some(name) => match self.mounts.get(name) {
+                Some(mount) => Ok((Some(mount), requested.clone())),
+                None => Err(LinkerError::ConfigError(format!(
+                    "default_mount {} does not exist",
+                    name
+                ))),
+            },
+            None => Ok((None, requested.clone())),
+        }
+    }
 
-                // transform to string
+    pub fn expand_placeholders(&mut self) -> Result<(), String> {
+        self.base_path = expand(&self.base_path, &[])?;
+        if let Some(hosts_file) = &self.hosts_file {
+            self.hosts_file = Some(expand(hosts_file, &[])?);
+        }
+        self.launcher.expand_placeholders()?;
+        for app in self.apps.iter_mut() {
+            app.expand_placeholders()?;
+        }
+        for mount in self.mounts.values_mut() {
+            mount.path = expand(&mount.path, &[])?;
+            if let Some(launcher) = mount.launcher.as_mut() {
+                launcher.expand_placeholders()?;
             }
-            None => Err("did not find home dir".to_string()),
         }
+        Ok(())
     }
 
-    #[cfg(target_os = "windows")]
-    fn open_file_with_dialog(&self, path_to_file: String) -> Result<(), String> {
-        // powershell.exe -command "openwith \"path\to\file\with\backslashes.pdf\""
+    fn hosts_file(&self) -> PathBuf {
+        PathBuf::from(
+            self.hosts_file
+                .as_deref()
+                .unwrap_or_else(|| default_hosts_file()),
+        )
+    }
 
-        println!("opening file dialog for file {}", path_to_file);
+    fn get_base_path(&self, mount: Option<&MountConfig>) -> Result<PathBuf, LinkerError> {
+        let base_path = mount.map(|m| &m.path).unwrap_or(&self.base_path);
+        canonicalize(base_path).map_err(|e| {
+            LinkerError::ConfigError(format!("Could not canonicalize {}: {}", base_path, e))
+        })
+    }
 
-        let output = Command::new("powershell")
-            .args(["-command", &format!("openwith \"{}\"", { path_to_file })])
-            .output()
-            .map_err(|e| format!("process did not finish successfully: {}", e))?;
+    // OneDrive may store names decomposed (NFD) while the request is always composed (NFC)
+    fn find_entry(dir: &Path, name: &OsStr) -> OsString {
+        if dir.join(name).symlink_metadata().is_ok() {
+            return name.to_owned();
+        }
+        let wanted = name.to_str().map(nfc);
+        read_dir(dir)
+            .ok()
+            .and_then(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.file_name())
+                    .find(|entry| entry.to_str().map(nfc) == wanted)
+            })
+            .unwrap_or_else(|| name.to_owned())
+    }
+
+    pub fn get_absolute_pdf_path(
+        &self,
+        mount: Option<&MountConfig>,
+        requested: &RequestedFile,
+    ) -> Result<String, LinkerError> {
+        let requested_path = requested.url_path();
+        println!("getting absolute path for {}", requested_path);
+        let root = self.get_base_path(mount)?;
 
-        if output.status.success() {
-            println!("{:?}", output);
-            Ok(())
+        // rel_path was normalized while parsing. anything but plain names, like a drive on
+        // windows, would leave base_path
+        let mut relative_path = PathBuf::new();
+        for component in Path::new(&requested.rel_path).components() {
+            match component {
+                Component::Normal(part) => relative_path.push(part),
+                _ => return Err(LinkerError::OutsideRoot(requested_path)),
+            }
+        }
+
+        let mut file_path = root.clone();
+        for part in relative_path.iter() {
+            file_path.push(SystemConfig::find_entry(&file_path, part));
+            if self.symlinks == SymlinkPolicy::Deny {
+                let is_symlink = file_path
+                    .symlink_metadata()
+                    .map(|m| m.file_type().is_symlink())
+                    .unwrap_or(false);
+                if is_symlink {
+                    return Err(LinkerError::SymlinkDenied(requested_path));
+                }
+            }
+        }
+
+        // canonicalize the path
+        let file_path = canonicalize(file_path).map_err(|e| match e.kind() {
+            ErrorKind::PermissionDenied => LinkerError::Forbidden(requested_path.clone()),
+            _ => LinkerError::NotFound(requested_path.clone()),
+        })?;
+
+        // symlinks may still point somewhere else
+        if self.symlinks != SymlinkPolicy::Follow && !file_path.starts_with(&root) {
+            return Err(LinkerError::OutsideRoot(requested_path));
+        }
+
+        // transform to string
+        let file_path = file_path
+            .to_str()
+            .ok_or(LinkerError::InvalidPath(requested_path))?
+            .to_string();
+
+        // remove prefix
+        if file_path.starts_with(r"\\?\") {
+            println!("removed prefix");
+            Ok(file_path.replacen(r"\\?\", "", 1))
         } else {
-            Err(format!(
-                "process failed. stderr: {:?}",
-                String::from_utf8(output.stderr).unwrap()
-            ))
+            Ok(file_path)
         }
     }
 
-    #[cfg(target_os = "windows")]
-    pub fn run(&self, path: &str) -> Result<(), String> {
-        let path_to_file = self.get_absolute_pdf_path(path)?;
-        assert_eq!(
-            r"C:\Users\tim\OneDrive\OneDrive - epfl.ch\test.pdf",
-            path_to_file
-        );
-        self.open_file_with_dialog(path_to_file)
+    pub fn run(
+        &self,
+        mount: Option<&MountConfig>,
+        requested: &RequestedFile,
+    ) -> Result<(), LinkerError> {
+        let path_to_file = self.get_absolute_pdf_path(mount, requested)?;
+        self.launch(mount, requested, &path_to_file)
+    }
+
+    fn launcher(
+        &self,
+        mount: Option<&MountConfig>,
+        path_to_file: &str,
+    ) -> Result<Box<dyn Launcher>, LinkerError> {
+        let app = find_app(&self.apps, Path::new(path_to_file))
+            .ok_or(LinkerError::NoApplication(path_to_file.to_string()))?;
+        let launcher = mount
+            .and_then(|m| m.launcher.as_ref())
+            .unwrap_or(&self.launcher);
+        Ok(app.launcher(launcher))
+    }
+
+    pub fn launch(
+        &self,
+        mount: Option<&MountConfig>,
+        requested: &RequestedFile,
+        path_to_file: &str,
+    ) -> Result<(), LinkerError> {
+        self.launcher(mount, path_to_file)?
+            .launch(path_to_file, requested.page, self.wait_for_exit)
+    }
+
+    // problems that only show up once a file is requested
+    fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.hostname.is_empty()
+            || self
+                .hostname
+                .contains(|c: char| c.is_whitespace() || "#/:".contains(c))
+        {
+            problems.push(format!("invalid hostname: {:?}", self.hostname));
+        }
+        if let Err(LinkerError::ConfigError(err)) = self.get_base_path(None) {
+            problems.push(err);
+        }
+        for (name, mount) in self.mounts.iter() {
+            if let Err(LinkerError::ConfigError(err)) = self.get_base_path(Some(mount)) {
+                problems.push(format!("mount {}: {}", name, err));
+            }
+        }
+        for name in self.mounts.keys() {
+            if name.is_empty() || name.contains('/') {
+                problems.push(format!(
+                    "mount names can not be empty or contain /: {}",
+                    name
+                ));
+            }
+        }
+        if let Some(name) = &self.default_mount {
+            if !self.mounts.contains_key(name) {
+                problems.push(format!("default_mount {} does not exist", name));
+            }
+        }
+        problems
     }
 }
 
 impl SecurityConfig {
-    fn matches_blacklist(&self, path: &str) -> bool {
-        SecurityConfig::matches_list(&self.blacklist, path)
+    fn access_rules<'a>(&'a self, mount: Option<&'a MountConfig>) -> AccessRules<'a> {
+        match mount.map(MountConfig::access_rules) {
+            Some(rules) if !rules.is_empty() => rules,
+            _ => AccessRules {
+                rules: &self.rules,
+                default_action: self.default_action,
+                blacklist: &self.blacklist,
+                whitelist: &self.whitelist,
+            },
+        }
     }
-    fn matches_whitelist(&self, path: &str) -> bool {
-        SecurityConfig::matches_list(&self.whitelist, path)
+    fn signature(secret: &str, path: &str, expires: Option<u64>) -> Hmac<Sha256> {
+        let expires = expires.map(|e| e.to_string()).unwrap_or_default();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("expected hmac to take keys of any length");
+        mac.update(format!("{}\n{}", expires, path).as_bytes());
+        mac
     }
-    fn matches_list(list: &Vec<Regex>, path: &str) -> bool {
-        list.iter().fold(false, |acc, r| r.is_match(path) || acc)
+
+    // query string that signs the path (including the mount prefix), None if no secret is set
+    pub fn sign(&self, path: &str, expires: Option<u64>) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let signature = to_hex(
+            &SecurityConfig::signature(secret, path, expires)
+                .finalize()
+                .into_bytes(),
+        );
+        Some(match expires {
+            Some(expires) => format!("sig={}&exp={}", signature, expires),
+            None => format!("sig={}", signature),
+        })
     }
-    fn allow_request(&self, request: &Request) -> bool {
-        vec![
-            self.allow_force_loopback(request),
-            self.allow_black_and_white_list(request),
+
+    fn allow_signature(&self, requested: &RequestedFile) -> bool {
+        let secret = match &self.secret {
+            Some(secret) => secret,
+            None => return true,
+        };
+        let expires = match requested.query_value("exp").map(str::parse::<u64>) {
+            Some(Ok(expires)) => Some(expires),
+            Some(Err(_)) => return false,
+            None => None,
+        };
+        if let Some(expires) = expires {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(u64::MAX);
+            if now > expires {
+                println!("link expired at {}", expires);
+                return false;
+            }
+        }
+        let signature = match requested.query_value("sig").and_then(from_hex) {
+            Some(signature) => signature,
+            None => return false,
+        };
+        SecurityConfig::signature(secret, &requested.full_path(), expires)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+
+    pub fn check_rules(&self, mount: Option<&MountConfig>, requested: &RequestedFile) -> Decision {
+        self.access_rules(mount).check(&requested.rel_path)
+    }
+    fn allow_request(
+        &self,
+        request: &Request,
+        mount: Option<&MountConfig>,
+        requested: &RequestedFile,
+    ) -> Result<(), LinkerError> {
+        self.allow_host(request)?;
+        let allowed = [
+            self.allow_client(request),
+            self.headers.allow_request(request),
+            self.check_rules(mount, requested).action == Action::Allow,
+            self.allow_signature(requested),
         ]
         .iter()
-        .all(|b| b == &true)
-    }
-    fn allow_force_loopback(&self, request: &Request) -> bool {
-        if self.force_loopback {
-            request
-                .remote_addr()
-                .and_then(|addr| Some(addr.ip().is_loopback()))
-                .unwrap_or(false)
-        } else {
-            true
+        .all(|b| b == &true);
+        match allowed {
+            true => Ok(()),
+            false => Err(LinkerError::Forbidden(requested.url_path())),
+        }
+    }
+    // admin endpoints are not files, so only the host, client, header and signature checks apply.
+    // other sites can not use them, not even the allowed origins
+    fn allow_admin_request(
+        &self,
+        request: &Request,
+        requested: &RequestedFile,
+    ) -> Result<(), LinkerError> {
+        self.allow_host(request)?;
+        let allowed = [
+            !is_cross_site(request.headers()),
+            self.allow_client(request),
+            self.headers.allow_request(request),
+            self.allow_signature(requested),
+        ]
+        .iter()
+        .all(|b| b == &true);
+        match allowed {
+            true => Ok(()),
+            false => Err(LinkerError::Forbidden(requested.url_path())),
+        }
+    }
+    fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.allowed_hosts.is_empty() {
+            problems.push("security.allowed_hosts can not be empty".to_string());
+        }
+        if let Some(Err(err)) = self.rate_limit.as_ref().map(RateLimit::validate) {
+            problems.push(err);
+        }
+        if self.secret.as_deref().map(str::is_empty) == Some(true) {
+            problems.push("security.secret can not be empty".to_string());
+        }
+        problems
+    }
+
+    fn allow_rate_limit(&self, request: &Request, limits: &Limits) -> bool {
+        match (&self.rate_limit, request.remote_addr()) {
+            (Some(rate_limit), Some(addr)) => limits.check_rate(rate_limit, addr.ip()),
+            _ => true,
+        }
+    }
+
+    fn allow_host(&self, request: &Request) -> Result<(), LinkerError> {
+        let host = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Host"))
+            .map(|header| header.value.as_str())
+            .unwrap_or_default();
+        if self
+            .allowed_hosts
+            .iter()
+            .any(|allowed| matches_host(host, allowed))
+        {
+            return Ok(());
         }
+        println!("host {:?} is not allowed", host);
+        Err(LinkerError::HostNotAllowed(host.to_string()))
     }
 
-    fn allow_black_and_white_list(&self, request: &Request) -> bool {
-        let url = request.url();
-        if self.matches_blacklist(url) {
-            if self.matches_whitelist(url) {
-                true
-            } else {
+    fn allow_client(&self, request: &Request) -> bool {
+        self.allow_remote_addr(request.remote_addr())
+    }
+
+    fn allow_remote_addr(&self, remote_addr: Option<&SocketAddr>) -> bool {
+        let loopback;
+        let clients = if !self.clients.is_empty() {
+            &self.clients
+        } else if self.force_loopback {
+            loopback = loopback_rules();
+            &loopback
+        } else {
+            return true;
+        };
+        match remote_addr {
+            // a unix socket, only this machine can connect
+            None => true,
+            Some(addr) if allow_client(clients, addr.ip()) => true,
+            addr => {
+                println!("client {:?} is not allowed", addr);
                 false
             }
-        } else {
-            true
         }
     }
 }
 
 impl ServerConfig {
-    pub fn get_server(&self) -> Result<Server, Box<dyn Error + Send + Sync + 'static>> {
-        Server::http((self.addr.as_str(), self.port))
-    }
-
-    pub fn handle_request(&self, request: Request, is_allowed: bool, did_succeed: Option<bool>) {
-        let response = if is_allowed {
-            if did_succeed.unwrap_or(false) {
-                if self.close_tab {
-                    let mut response = Response::from_string(
-                        "<script>window.close()</script>tab should close now",
-                    );
-                    response.add_header(
-                        Header::from_bytes(&b"Content-Type"[..], &b"text/html"[..]).unwrap(),
-                    );
-                    response
-                } else {
-                    Response::from_string("ok")
+    // false if the same file was opened within debounce_ms
+    pub fn should_launch(&self, limits: &Limits, path_to_file: &str) -> bool {
+        let window = Duration::from_millis(self.debounce_ms);
+        limits.debouncer.should_launch(path_to_file, window)
+    }
+
+    // listen or addr and port serve http. tls listens on addr, port serves https
+    // instead of http if tls has no port of its own
+    pub fn get_servers(&self) -> Result<Vec<Server>, LinkerError> {
+        let https_port = self.tls.as_ref().map(|tls| tls.port.unwrap_or(self.port));
+        let mut servers = vec![];
+        if !self.listen.is_empty() {
+            for addr in self.listen.iter() {
+                servers.push(addr.bind()?);
+            }
+        } else if https_port != Some(self.port) {
+            servers.push(ListenAddr::Tcp(format!("{}:{}", self.addr, self.port)).bind()?);
+        }
+        if let (Some(tls), Some(port)) = (&self.tls, https_port) {
+            for host in self.tls_hosts() {
+                servers.push(tls.listen(host, port)?);
+            }
+        }
+        Ok(servers)
+    }
+
+    // https is served on the hosts of listen if it is set, not on addr
+    fn tls_hosts(&self) -> Vec<&str> {
+        match self.listen.is_empty() {
+            true => vec![self.addr.as_str()],
+            false => self.listen.iter().filter_map(ListenAddr::host).collect(),
+        }
+    }
+
+    // the scheme and port of links, https if it is served
+    fn link_base(&self) -> (&str, u16) {
+        let http_port = self.listen.iter().find_map(ListenAddr::port);
+        match &self.tls {
+            Some(tls) => ("https", tls.port.unwrap_or(self.port)),
+            None => ("http", http_port.unwrap_or(self.port)),
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = self.responses.validate();
+        if self.port == 0 {
+            problems.push("server.port can not be 0".to_string());
+        }
+        if let Err(err) = (self.addr.as_str(), self.port).to_socket_addrs() {
+            problems.push(format!("server.addr {} is invalid: {}", self.addr, err));
+        }
+        problems.extend(self.listen.iter().filter_map(ListenAddr::validate));
+        if self.workers == 0 {
+            problems.push("server.workers needs to be at least 1".to_string());
+        }
+        if let Some(tls) = &self.tls {
+            problems.extend(tls.validate());
+            if self.tls_hosts().is_empty() {
+                problems.push("server.tls needs a tcp address in server.listen".to_string());
+            }
+            let https_port = tls.port.unwrap_or(self.port);
+            for addr in self.listen.iter() {
+                if addr.port() == Some(https_port) {
+                    problems.push(format!(
+                        "server.listen {} uses the https port, set server.tls.port to another port",
+                        addr
+                    ));
                 }
-            } else {
-                Response::from_string("failed to start. check logs")
             }
-        } else {
-            Response::from_string("does not comply").with_status_code(401)
-        };
+        }
+        problems
+    }
+
+    pub fn handle_request(&self, request: Request, file: &str, result: Result<(), LinkerError>) {
+        let (status, page) = self.responses.render(file, &result, self.close_tab);
+        ServerConfig::respond(request, status, page);
+    }
+
+    fn respond(request: Request, status: u16, page: String) {
+        let response = Response::from_string(page)
+            .with_status_code(status)
+            .with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..]).unwrap(),
+            );
         if let Err(err) = request.respond(response) {
             println!("could not respond to request: {}", err);
         }
@@ -214,80 +779,619 @@ impl ServerConfig {
 }
 
 impl Linker {
-    pub fn read_config(path: &str) -> Linker {
-        match File::open(path) {
-            Ok(reader) => match from_reader(reader) {
-                Err(err) => {
-                    println!(
-                        "Warning: using default configuration. Could not parse file: {}",
-                        err
-                    );
-                    Linker::default()
-                }
-                Ok(cfg) => cfg,
-            },
-            Err(_) => {
-                println!("Warning: using default configuration. Could not find file.");
-                Linker::default()
+    // falls back to the default configuration only if allow_default is set
+    pub fn read_config(layers: &Layers, allow_default: bool) -> Result<Linker, LinkerError> {
+        match Linker::load_config(layers) {
+            Err(err) if allow_default => {
+                println!("Warning: using default configuration. {}", err);
+                let mut linker = Linker::default();
+                linker
+                    .expand_placeholders()
+                    .expect("expected default configuration to expand");
+                Ok(linker)
             }
+            loaded => loaded,
         }
     }
 
-    pub fn allow_request(&self, request: &Request) -> bool {
-        self.security.allow_request(request)
+    pub fn load_config(layers: &Layers) -> Result<Linker, LinkerError> {
+        let (config, _) = layers.merge().map_err(LinkerError::ConfigError)?;
+        let mut linker: Linker = serde_yaml::from_value(config)
+            .map_err(|e| LinkerError::ConfigError(Linker::locate_error(layers, e)))?;
+        linker
+            .expand_placeholders()
+            .map_err(LinkerError::ConfigError)?;
+        let problems = linker.validate();
+        if !problems.is_empty() {
+            return Err(LinkerError::ConfigError(problems.join("\n")));
+        }
+        Ok(linker)
     }
 
-    pub fn get_server(&self) -> Result<Server, Box<dyn Error + Send + Sync + 'static>> {
-        self.server.get_server()
+    // the merged configuration has no line numbers. if the error is in the file itself,
+    // parsing the file on its own tells where
+    fn locate_error(layers: &Layers, err: serde_yaml::Error) -> String {
+        let located = layers
+            .file
+            .as_ref()
+            .and_then(|path| Some((path, read_to_string(path).ok()?)))
+            .and_then(|(path, contents)| {
+                let file_err = serde_yaml::from_str::<Linker>(&contents).err()?;
+                let location = file_err.location()?;
+                let suffix = format!(" at line {} column {}", location.line(), location.column());
+                let message = file_err.to_string();
+                message.contains(&err.to_string()).then(|| {
+                    format!(
+                        "{}:{}:{}: {}",
+                        path.display(),
+                        location.line(),
+                        location.column(),
+                        message.trim_end_matches(&suffix)
+                    )
+                })
+            });
+        located
+            .unwrap_or_else(|| format!("invalid configuration after applying overrides: {}", err))
     }
 
-    pub fn handle_request(&self, request: Request) {
-        let is_allowed = self.allow_request(&request);
-        let did_succeed = if is_allowed {
-            println!("Request passed all security-checks.");
-            Some(
-                self.system
-                    .run(&request.url())
-                    .map_err(|e| {
-                        println!("failed to start: {}", e);
-                        ()
-                    })
-                    .is_ok(),
-            )
-        } else {
-            None
+    fn expand_placeholders(&mut self) -> Result<(), String> {
+        self.server.responses.expand_placeholders()?;
+        if let Some(tls) = self.server.tls.as_mut() {
+            tls.expand_placeholders()?;
+        }
+        for addr in self.server.listen.iter_mut() {
+            addr.expand_placeholders()?;
+        }
+        if let Some(secret) = &self.security.secret {
+            self.security.secret = Some(expand(secret, &[])?);
+        }
+        for host in self.security.allowed_hosts.iter_mut() {
+            *host = expand(host, &["hostname"])?.replace("{{hostname}}", &self.system.hostname);
+        }
+        self.system.expand_placeholders()
+    }
+
+    // patterns are already checked while parsing, this finds what only shows up later
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = self.security.validate();
+        problems.extend(self.server.validate());
+        problems.extend(self.system.validate());
+        problems
+    }
+
+    // the configuration after defaults and placeholders were applied, without the secret
+    pub fn effective_config(&self) -> Result<Value, LinkerError> {
+        let mut config =
+            serde_yaml::to_value(self).map_err(|e| LinkerError::ConfigError(e.to_string()))?;
+        if let Some(secret) = config
+            .get_mut("security")
+            .and_then(|security| security.get_mut("secret"))
+        {
+            *secret = "<hidden>".into();
+        }
+        Ok(config)
+    }
+
+    pub fn allow_request(
+        &self,
+        request: &Request,
+        mount: Option<&MountConfig>,
+        requested: &RequestedFile,
+    ) -> Result<(), LinkerError> {
+        self.security.allow_request(request, mount, requested)
+    }
+
+    // a signed link to paste into notion. an error if security.secret is not set, unsigned
+    // links are not checked then and need no command
+    pub fn signed_link(&self, path: &str, expires_in: Option<u64>) -> Result<String, String> {
+        let requested = RequestedFile::parse(&format!("/{}", path.trim_start_matches('/')))
+            .map_err(|e| e.to_string())?;
+        let expires = expires_in.map(|seconds| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+                + seconds
+        });
+        let (scheme, port) = self.server.link_base();
+        let port = match (scheme, port) {
+            ("http", 80) | ("https", 443) => String::new(),
+            (_, port) => format!(":{}", port),
         };
-        self.server.handle_request(request, is_allowed, did_succeed)
+        let link = format!(
+            "{}://{}{}/{}",
+            scheme,
+            self.system.hostname,
+            port,
+            percent_encode(&requested.rel_path)
+        );
+        match self.security.sign(&requested.rel_path, expires) {
+            Some(query) => Ok(format!("{}?{}", link, query)),
+            None => Err("security.secret is not set, links do not need to be signed".to_string()),
+        }
+    }
+
+    // what a request for the url would do, without launching anything
+    pub fn resolve(&self, url: &str) -> Result<Resolution, LinkerError> {
+        let requested = RequestedFile::parse(url)?;
+        let (mount, requested) = self.system.find_mount(&requested)?;
+        let decision = self.security.check_rules(mount, &requested);
+        let path = self.system.get_absolute_pdf_path(mount, &requested);
+        let command = path.as_ref().ok().map(|path| {
+            self.system
+                .launcher(mount, path)
+                .map(|launcher| format!("{:?}", launcher.command(path, requested.page)))
+        });
+        Ok(Resolution {
+            mount: requested.mount.clone(),
+            decision,
+            path,
+            command,
+        })
+    }
+
+    // opens a file from the command line. only the rules apply, there is no client to check
+    pub fn open(&self, path: &str) -> Result<String, LinkerError> {
+        let requested = RequestedFile::parse(&format!("/{}", path.trim_start_matches('/')))?;
+        let (mount, requested) = self.system.find_mount(&requested)?;
+        let decision = self.security.check_rules(mount, &requested);
+        if decision.action != Action::Allow {
+            return Err(LinkerError::Forbidden(format!(
+                "{}, {}",
+                requested.url_path(),
+                decision
+            )));
+        }
+        let path_to_file = self.system.get_absolute_pdf_path(mount, &requested)?;
+        self.system.launch(mount, &requested, &path_to_file)?;
+        Ok(path_to_file)
+    }
+
+    // points system.hostname to this machine in the hosts file. false if it already was
+    pub fn install_hostname(&self) -> Result<bool, LinkerError> {
+        hosts::install(&self.system.hosts_file(), &self.system.hostname)
+            .map_err(LinkerError::ConfigError)
+    }
+
+    pub fn uninstall_hostname(&self) -> Result<bool, LinkerError> {
+        hosts::uninstall(&self.system.hosts_file()).map_err(LinkerError::ConfigError)
+    }
+
+    // the absolute path of the file that was opened. None if the page may still be in the
+    // fragment: file.pdf#page=14 arrives as file.pdf, fragment_redirect asks for file.pdf?page=14
+    fn process_request(
+        &self,
+        request: &Request,
+        limits: &Limits,
+    ) -> Result<Option<String>, LinkerError> {
+        if !self.security.allow_rate_limit(request, limits) {
+            return Err(LinkerError::RateLimited);
+        }
+        // a HEAD from a link preview or a POST from a form must not open anything
+        if request.method() != &Method::Get {
+            return Err(LinkerError::MethodNotAllowed(request.method().to_string()));
+        }
+        let requested = RequestedFile::parse(request.url())?;
+        let (mount, requested) = self.system.find_mount(&requested)?;
+        self.allow_request(request, mount, &requested)?;
+        println!("Request passed all security-checks.");
+        if requested.query_value("page").is_none() {
+            return Ok(None);
+        }
+        let path_to_file = self.system.get_absolute_pdf_path(mount, &requested)?;
+        if self.server.should_launch(limits, &path_to_file) {
+            if let Err(err) = self.system.launch(mount, &requested, &path_to_file) {
+                limits.debouncer.forget(&path_to_file);
+                return Err(err);
+            }
+        } else {
+            println!("{} was opened just now, not opening it again", path_to_file);
+        }
+        Ok(Some(path_to_file))
     }
-    pub fn start(&self) {
-        let server = self
-            .get_server()
-            .expect("expected server to start. Is the port blocked or security to strict?");
-        println!("Started server. Listening on port {}", self.server.port);
-        for request in server.incoming_requests() {
-            println!("Received request.");
-            self.handle_request(request)
+
+    fn check_shutdown(&self, request: &Request, limits: &Limits) -> Result<(), LinkerError> {
+        if !self.security.allow_rate_limit(request, limits) {
+            return Err(LinkerError::RateLimited);
+        }
+        if request.method() != &Method::Post {
+            return Err(LinkerError::MethodNotAllowed(request.method().to_string()));
+        }
+        let requested = RequestedFile::parse(request.url())?;
+        self.security.allow_admin_request(request, &requested)?;
+        Ok(())
+    }
+
+    fn handle_shutdown(&self, request: Request, limits: &Limits) -> bool {
+        if let Err(err) = self.check_shutdown(&request, limits) {
+            println!("refused to shut down: {}", err);
+            self.server.handle_request(request, SHUTDOWN_PATH, Err(err));
+            return false;
+        }
+        let response = Response::from_string("shutting down").with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"text/plain; charset=utf-8"[..]).unwrap(),
+        );
+        if let Err(err) = request.respond(response) {
+            println!("could not respond to request: {}", err);
         }
+        true
+    }
+
+    // true if the request asked the server to shut down
+    pub fn handle_request(&self, request: Request, limits: &Limits) -> bool {
+        if request.url().split(['?', '#']).next() == Some(SHUTDOWN_PATH) {
+            return self.handle_shutdown(request, limits);
+        }
+        let (file, result) = match self.process_request(&request, limits) {
+            Ok(Some(path_to_file)) => (path_to_file, Ok(())),
+            Ok(None) => {
+                let page = fragment_redirect(request.url());
+                ServerConfig::respond(request, 200, page);
+                return false;
+            }
+            Err(err) => {
+                println!("failed to start: {}", err);
+                let path = request.url().split(['?', '#']).next().unwrap_or_default();
+                let file = percent_decode(path, false).unwrap_or_else(|_| path.to_string());
+                (file, Err(err))
+            }
+        };
+        self.server.handle_request(request, &file, result);
+        false
+    }
+
+    pub fn listen(&self) -> Result<Vec<Server>, LinkerError> {
+        self.server.get_servers()
+    }
+
+    // a different address or certificate needs new listening sockets
+    pub fn same_address(&self, other: &Linker) -> bool {
+        self.server.addr == other.server.addr
+            && self.server.port == other.server.port
+            && self.server.listen == other.server.listen
+            && self.server.tls == other.server.tls
+    }
+
+    // creates the certificate and key of server.tls, or the default paths if it is not set
+    pub fn gen_cert(&self) -> Result<TlsConfig, LinkerError> {
+        let mut tls = self.server.tls.clone().unwrap_or_default();
+        tls.expand_placeholders()
+            .and_then(|_| tls.gen_cert(&self.system.hostname))
+            .map_err(LinkerError::ConfigError)?;
+        Ok(tls)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Linker;
+    use super::{Linker, SecurityConfig, ServerConfig};
+    #[cfg(unix)]
+    use super::{LinkerError, SymlinkPolicy, SystemConfig};
+    use crate::request::RequestedFile;
+    #[cfg(unix)]
+    use crate::rules::Action;
+    #[cfg(unix)]
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    #[cfg(unix)]
+    use std::path::{Path, PathBuf};
+
+    #[cfg(unix)]
+    fn temp_tree(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("xodo-linker-{}-{}", std::process::id(), name));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(dir.join("root/course")).unwrap();
+        File::create(dir.join("root/course/lecture.pdf")).unwrap();
+        File::create(dir.join("outside.pdf")).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    fn resolve(system: &SystemConfig, url: &str) -> Result<String, LinkerError> {
+        let (mount, requested) = system.find_mount(&RequestedFile::parse(url)?)?;
+        system.get_absolute_pdf_path(mount, &requested)
+    }
+
+    #[cfg(unix)]
+    fn system_config(dir: &Path, symlinks: SymlinkPolicy) -> SystemConfig {
+        SystemConfig {
+            base_path: dir.join("root").to_str().unwrap().to_string(),
+            symlinks,
+            ..SystemConfig::default()
+        }
+    }
+
+    #[test]
+    fn parses_bundled_config() {
+        let config = include_str!("config.yaml");
+        serde_yaml::from_str::<Linker>(config).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_invalid_config() {
+        use crate::config::Layers;
+        use std::fs::write;
+
+        let dir = temp_tree("config");
+        let path = dir.join("config.yaml");
+        let config = include_str!("config.yaml").replace(
+            r"{{home_dir}}\\OneDrive\\ONEDRI~1",
+            dir.join("root").to_str().unwrap(),
+        );
+        write(&path, &config).unwrap();
+        let layers = |cli: &[(&str, &str)]| Layers {
+            file: Some(path.clone()),
+            cli: cli
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            ..Layers::default()
+        };
+        let linker = Linker::load_config(&layers(&[])).unwrap();
+        assert!(linker.security.allowed_hosts.contains(&"xodo".to_string()));
+
+        let typo = layers(&[("security.allowed_hosts", "[\"{{hostnme}}\"]")]);
+        match Linker::load_config(&typo) {
+            Err(LinkerError::ConfigError(err)) => assert!(err.contains("hostnme"), "{}", err),
+            result => panic!(
+                "expected a configuration error, got {:?}",
+                result.map(|_| ())
+            ),
+        }
+
+        let err = Linker::load_config(&layers(&[("server.port", "0")])).unwrap_err();
+        assert_eq!(
+            err,
+            LinkerError::ConfigError("server.port can not be 0".to_string())
+        );
+        assert!(Linker::load_config(&layers(&[("server.prot", "80")])).is_err());
+        assert!(Linker::read_config(&layers(&[("server.prot", "80")]), true).is_ok());
+
+        write(&path, config.replace("    close_tab:", "    close_tabs:")).unwrap();
+        let line = config
+            .lines()
+            .position(|line| line.starts_with("    close_tab:"))
+            .unwrap()
+            + 1;
+        match Linker::load_config(&layers(&[])) {
+            Err(LinkerError::ConfigError(err)) => assert!(
+                err.starts_with(&format!(
+                    "{}:{}:5: server: unknown field `close_tabs`",
+                    path.display(),
+                    line
+                )),
+                "{}",
+                err
+            ),
+            result => panic!(
+                "expected a configuration error, got {:?}",
+                result.map(|_| ())
+            ),
+        }
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn merges_overrides_onto_defaults() {
+        use crate::config::Layers;
+
+        let dir = temp_tree("overrides");
+        let layers = Layers {
+            cli: vec![
+                ("server.port".to_string(), "8080".to_string()),
+                (
+                    "system.base_path".to_string(),
+                    dir.join("root").to_str().unwrap().to_string(),
+                ),
+                ("system.hostname".to_string(), "xodo".to_string()),
+            ],
+            ..Layers::default()
+        };
+        let linker = Linker::load_config(&layers).unwrap();
+        assert_eq!(linker.server.port, 8080);
+        assert!(linker.server.close_tab);
+        assert!(linker.security.force_loopback);
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn serves_tls_on_listen_hosts() {
+        let server = |yaml: &str| serde_yaml::from_str::<ServerConfig>(yaml).unwrap();
+        let listening = server("{listen: [\"127.0.0.1:8080\", \"[::1]:8080\"], tls: {port: 8443}}");
+        assert_eq!(listening.tls_hosts(), vec!["127.0.0.1", "::1"]);
+        assert_eq!(server("{tls: {}}").tls_hosts(), vec!["0.0.0.0"]);
+
+        let problems = server("{listen: [\"unix:/tmp/x.sock\"], tls: {}}").validate();
+        assert!(problems.contains(&"server.tls needs a tcp address in server.listen".to_string()));
+        let problems = server("{listen: [\"127.0.0.1:443\"], tls: {port: 443}}").validate();
+        assert!(problems.iter().any(|p| p.contains("uses the https port")));
+    }
+
+    #[test]
+    fn checks_signed_links() {
+        let security = SecurityConfig {
+            secret: Some("correct horse battery staple".to_string()),
+            ..SecurityConfig::default()
+        };
+        let check = |url: &str| security.allow_signature(&RequestedFile::parse(url).unwrap());
+
+        // links signed by earlier versions stay valid: hmac-sha256 of "<exp>\n<path>"
+        assert_eq!(
+            security.sign("x.pdf", None).unwrap(),
+            "sig=0613fd145c2382a654a88ac29c811f5b093bafc7d3920b55694704639e03080f"
+        );
+
+        let query = security.sign("course/Übung.pdf", None).unwrap();
+        assert!(check(&format!("/course/%C3%9Cbung.pdf?{}&page=3", query)));
+        assert!(!check(&format!("/course/other.pdf?{}", query)));
+        assert!(!check("/course/%C3%9Cbung.pdf"));
+        assert!(!check("/course/%C3%9Cbung.pdf?sig=00"));
+
+        let query = security.sign("x.pdf", Some(u64::MAX)).unwrap();
+        assert!(check(&format!("/x.pdf?{}", query)));
+        let expired = security.sign("x.pdf", Some(1)).unwrap();
+        assert!(!check(&format!("/x.pdf?{}", expired)));
+        assert!(!check(&format!(
+            "/x.pdf?{}",
+            query.replace("sig=", "sig=zz")
+        )));
+        assert!(!check("/x.pdf?sig=%2B%2B"));
+        // the expiry is part of the signature
+        let extended = query.replace(&u64::MAX.to_string(), &(u64::MAX - 1).to_string());
+        assert!(!check(&format!("/x.pdf?{}", extended)));
+    }
+
+    #[test]
+    fn defaults_to_loopback_clients() {
+        let security: SecurityConfig = serde_yaml::from_str("default_action: deny").unwrap();
+        let local = "127.0.0.1:50000".parse().unwrap();
+        let remote = "192.168.10.20:50000".parse().unwrap();
+        assert!(security.allow_remote_addr(Some(&local)));
+        assert!(!security.allow_remote_addr(Some(&remote)));
+
+        let open: SecurityConfig = serde_yaml::from_str("force_loopback: false").unwrap();
+        assert!(open.allow_remote_addr(Some(&remote)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_inside_base_path() {
+        let dir = temp_tree("inside");
+        let system = system_config(&dir, SymlinkPolicy::WithinRoot);
+        assert_eq!(
+            resolve(&system, "/course/../course/./lecture.pdf"),
+            Ok(dir
+                .join("root/course/lecture.pdf")
+                .canonicalize()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string())
+        );
+        assert_eq!(
+            resolve(&system, "/course/missing.pdf"),
+            Err(LinkerError::NotFound("/course/missing.pdf".to_string()))
+        );
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_without_launching() {
+        let dir = temp_tree("resolve");
+        let linker = Linker {
+            system: system_config(&dir, SymlinkPolicy::WithinRoot),
+            ..Linker::default()
+        };
+        let resolution = linker.resolve("/course/lecture.pdf#page=2").unwrap();
+        assert_eq!(resolution.mount, None);
+        assert_eq!(resolution.decision.action, Action::Allow);
+        assert!(resolution.path.unwrap().ends_with("course/lecture.pdf"));
+        assert!(resolution.command.unwrap().is_ok());
 
+        let resolution = linker.resolve("/course/missing.exe").unwrap();
+        assert_eq!(resolution.decision.action, Action::Deny);
+        assert!(resolution.path.is_err());
+        assert!(resolution.command.is_none());
+        assert!(linker.open("course/missing.exe").is_err());
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
     #[test]
-    fn opens_dialog() {
-        Linker::default()
-            .system
-            .open_file_with_dialog(r"C:\Users\tim\Downloads\test.pdf".to_string())
+    fn resolves_decomposed_file_names() {
+        let dir = temp_tree("unicode");
+        File::create(dir.join("root/course/U\u{308}bung.pdf")).unwrap();
+        let system = system_config(&dir, SymlinkPolicy::WithinRoot);
+        assert!(resolve(&system, "/course/%C3%9Cbung.pdf")
             .unwrap()
+            .ends_with("course/U\u{308}bung.pdf"));
+        remove_dir_all(dir).unwrap();
     }
+
+    #[cfg(unix)]
     #[test]
-    fn opens_dialog_onedrive() {
-        Linker::default()
-            .system
-            // .open_file_with_dialog(r"C:\Users\tim\OneDrive\OneDrive - epfl.ch\test.pdf".to_string())
-            .open_file_with_dialog(r"C:\Users\tim\OneDrive\OneDrive - epfl.ch\test.pdf".to_string())
+    fn resolves_mounts() {
+        let dir = temp_tree("mounts");
+        create_dir_all(dir.join("papers")).unwrap();
+        File::create(dir.join("papers/paper.pdf")).unwrap();
+        let mut system = system_config(&dir, SymlinkPolicy::WithinRoot);
+        system.mounts = serde_yaml::from_str(&format!(
+            "epfl: {{path: {}}}\npapers: {{path: {}}}",
+            dir.join("root").display(),
+            dir.join("papers").display()
+        ))
+        .unwrap();
+
+        assert!(resolve(&system, "/epfl/course/lecture.pdf").is_ok());
+        assert!(resolve(&system, "/papers/paper.pdf").is_ok());
+        assert!(resolve(&system, "/paper.pdf").is_err());
+        // ".." is resolved before the mount is chosen, so it can only switch to another mount
+        assert!(resolve(&system, "/papers/../epfl/course/lecture.pdf")
             .unwrap()
+            .ends_with("root/course/lecture.pdf"));
+        assert_eq!(
+            resolve(&system, "/papers/../../root/course/lecture.pdf"),
+            Err(LinkerError::OutsideRoot(
+                "/papers/../../root/course/lecture.pdf".to_string()
+            ))
+        );
+
+        system.default_mount = Some("papers".to_string());
+        assert!(resolve(&system, "/paper.pdf").is_ok());
+        system.default_mount = Some("missing".to_string());
+        assert!(resolve(&system, "/paper.pdf").is_err());
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_path_traversal() {
+        let dir = temp_tree("traversal");
+        let system = system_config(&dir, SymlinkPolicy::Follow);
+        for path in [
+            "/../outside.pdf",
+            "/course/../../outside.pdf",
+            "//outside.pdf",
+        ] {
+            assert_eq!(
+                resolve(&system, path),
+                Err(LinkerError::OutsideRoot(path.to_string()))
+            );
+        }
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn applies_symlink_policy() {
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_tree("symlinks");
+        symlink(dir.join("outside.pdf"), dir.join("root/escape.pdf")).unwrap();
+        symlink(dir.join("root/course"), dir.join("root/linked")).unwrap();
+
+        let within_root = system_config(&dir, SymlinkPolicy::WithinRoot);
+        assert_eq!(
+            resolve(&within_root, "/escape.pdf"),
+            Err(LinkerError::OutsideRoot("/escape.pdf".to_string()))
+        );
+        assert!(resolve(&within_root, "/linked/lecture.pdf").is_ok());
+
+        let follow = system_config(&dir, SymlinkPolicy::Follow);
+        assert!(resolve(&follow, "/escape.pdf").is_ok());
+
+        let deny = system_config(&dir, SymlinkPolicy::Deny);
+        assert_eq!(
+            resolve(&deny, "/linked/lecture.pdf"),
+            Err(LinkerError::SymlinkDenied(
+                "/linked/lecture.pdf".to_string()
+            ))
+        );
+        assert!(resolve(&deny, "/course/lecture.pdf").is_ok());
+
+        remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,174 @@
+use crate::error::LinkerError;
+use crate::template::expand;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::ToSocketAddrs;
+use std::path::PathBuf;
+use tiny_http::Server;
+
+// an entry of server.listen: "127.0.0.1:8080", "[::1]:8080" or "unix:/run/user/1000/xodo.sock"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum ListenAddr {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl TryFrom<String> for ListenAddr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.strip_prefix("unix:") {
+            Some("") => Err("unix: needs a path".to_string()),
+            Some(path) => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            None => match value.rsplit_once(':').map(|(_, port)| port.parse::<u16>()) {
+                Some(Ok(_)) => Ok(ListenAddr::Tcp(value)),
+                _ => Err(format!("{} needs a port, e.g. 127.0.0.1:8080", value)),
+            },
+        }
+    }
+}
+
+impl From<ListenAddr> for String {
+    fn from(addr: ListenAddr) -> Self {
+        addr.to_string()
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl ListenAddr {
+    pub fn expand_placeholders(&mut self) -> Result<(), String> {
+        match self {
+            ListenAddr::Tcp(addr) => *addr = expand(addr, &[])?,
+            ListenAddr::Unix(path) => {
+                let expanded = match path.to_str() {
+                    Some(path) => expand(path, &[])?,
+                    None => return Ok(()),
+                };
+                *path = PathBuf::from(expanded);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            ListenAddr::Tcp(addr) => addr.rsplit_once(':')?.1.parse().ok(),
+            ListenAddr::Unix(_) => None,
+        }
+    }
+
+    // the address without the port, e.g. ::1 for [::1]:8080
+    pub fn host(&self) -> Option<&str> {
+        match self {
+            ListenAddr::Tcp(addr) => {
+                let host = addr.rsplit_once(':')?.0;
+                Some(host.trim_start_matches('[').trim_end_matches(']'))
+            }
+            ListenAddr::Unix(_) => None,
+        }
+    }
+
+    pub fn validate(&self) -> Option<String> {
+        match self {
+            ListenAddr::Tcp(addr) => match addr.to_socket_addrs() {
+                Err(err) => Some(format!("server.listen {} is invalid: {}", addr, err)),
+                Ok(_) if self.port() == Some(0) => {
+                    Some(format!("server.listen {} needs a port other than 0", addr))
+                }
+                Ok(_) => None,
+            },
+            ListenAddr::Unix(_) if !cfg!(unix) => {
+                Some(format!("server.listen {} needs a unix system", self))
+            }
+            ListenAddr::Unix(_) => None,
+        }
+    }
+
+    pub fn bind(&self) -> Result<Server, LinkerError> {
+        let server = match self {
+            ListenAddr::Tcp(addr) => Server::http(addr.as_str()),
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                remove_stale_socket(path);
+                Server::http_unix(path)
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => Err("unix sockets need a unix system".into()),
+        };
+        server.map_err(|e| {
+            LinkerError::ConfigError(format!(
+                "could not listen on {}. Is the port blocked? {}",
+                self, e
+            ))
+        })
+    }
+}
+
+// the socket file of a linker that did not stop cleanly is left behind and blocks the path.
+// it is only removed if nothing answers on it anymore
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    let is_socket = path
+        .symlink_metadata()
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket && UnixStream::connect(path).is_err() {
+        println!("removing stale socket {}", path.display());
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ListenAddr;
+    use std::path::PathBuf;
+
+    #[test]
+    fn parses_addresses() {
+        let parse = |addr: &str| ListenAddr::try_from(addr.to_string());
+        assert_eq!(
+            parse("127.0.0.1:8080"),
+            Ok(ListenAddr::Tcp("127.0.0.1:8080".to_string()))
+        );
+        assert_eq!(parse("[::1]:8080").unwrap().port(), Some(8080));
+        assert_eq!(parse("[::1]:8080").unwrap().host(), Some("::1"));
+        assert_eq!(parse("unix:/tmp/x.sock").unwrap().host(), None);
+        assert_eq!(
+            parse("unix:/run/user/1000/xodo.sock"),
+            Ok(ListenAddr::Unix(PathBuf::from("/run/user/1000/xodo.sock")))
+        );
+        assert_eq!(
+            parse("unix:/tmp/x.sock").unwrap().to_string(),
+            "unix:/tmp/x.sock"
+        );
+        assert!(parse("127.0.0.1").is_err());
+        assert!(parse("[::1]").is_err());
+        assert!(parse("unix:").is_err());
+        assert!(parse("localhost:0").unwrap().validate().is_some());
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        std::env::set_var("XODO_LINKER_TEST_RUNTIME_DIR", "/run/user/1000");
+        let mut addr =
+            ListenAddr::try_from("unix:{{env:XODO_LINKER_TEST_RUNTIME_DIR}}/xodo.sock".to_string())
+                .unwrap();
+        addr.expand_placeholders().unwrap();
+        assert_eq!(
+            addr,
+            ListenAddr::Unix(PathBuf::from("/run/user/1000/xodo.sock"))
+        );
+    }
+}
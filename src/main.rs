@@ -1,7 +1,44 @@
+// no console window for serve, see console.rs
 #![windows_subsystem = "windows"]
+pub mod apps;
+pub mod cli;
+pub mod clients;
+pub mod config;
+pub mod console;
+pub mod error;
+pub mod headers;
+pub mod hosts;
+pub mod launcher;
+pub mod limits;
 pub mod linker;
-use linker::Linker;
+pub mod listen;
+pub mod request;
+pub mod responses;
+pub mod rules;
+pub mod service;
+pub mod shutdown;
+pub mod template;
+pub mod tls;
+pub mod unicode;
+pub mod workers;
+use cli::{Cli, Command, USAGE};
+use console::attach_parent_console;
+use std::env;
+use std::process;
 
 fn main() {
-    Linker::read_config("./src/config.yaml").start();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match Cli::parse(&args) {
+        Ok(cli) => {
+            if cli.command != Command::Serve {
+                attach_parent_console();
+            }
+            process::exit(cli.run())
+        }
+        Err(err) => {
+            attach_parent_console();
+            println!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    }
 }
@@ -0,0 +1,232 @@
+use crate::error::LinkerError;
+use crate::unicode::nfc;
+use substring::Substring;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RequestedFile {
+    pub rel_path: String,
+    pub page: Option<u32>,
+    pub query: Vec<(String, String)>,
+    // set once rel_path has been made relative to a mount
+    pub mount: Option<String>,
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+pub fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn percent_decode(input: &str, plus_as_space: bool) -> Result<String, String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let value = bytes
+                    .get(i + 1)
+                    .and_then(|h| hex_value(*h))
+                    .zip(bytes.get(i + 2).and_then(|l| hex_value(*l)))
+                    .map(|(h, l)| h << 4 | l)
+                    .ok_or(format!("invalid percent-encoding in {}", input))?;
+                decoded.push(value);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    let decoded =
+        String::from_utf8(decoded).map_err(|_| format!("{} is not valid utf-8", input))?;
+    if decoded.contains('\0') {
+        return Err(format!("{} contains a null byte", input));
+    }
+    Ok(decoded)
+}
+
+fn parse_params(input: &str) -> Result<Vec<(String, String)>, String> {
+    input
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key, true)?, percent_decode(value, true)?))
+        })
+        .collect()
+}
+
+// "page=" is no page, the redirect page sends it for links without #page=
+fn find_page(params: &[(String, String)]) -> Result<Option<u32>, String> {
+    match params.iter().find(|(key, _)| key == "page") {
+        Some((_, page)) if page.is_empty() => Ok(None),
+        Some((_, page)) => page
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid page {}", page)),
+        None => Ok(None),
+    }
+}
+
+// resolves "." and ".." once, so the rules check the same path that is opened.
+// "course/../secret/x.pdf" becomes "secret/x.pdf"
+fn normalize(rel_path: &str) -> Result<String, LinkerError> {
+    let url_path = format!("/{}", rel_path);
+    if rel_path.is_empty() {
+        return Ok(String::new());
+    }
+    let mut parts = vec![];
+    for (i, part) in rel_path.split('/').enumerate() {
+        match part {
+            // "//file.pdf" would be an absolute path
+            "" if i == 0 => return Err(LinkerError::OutsideRoot(url_path)),
+            "" => {
+                return Err(LinkerError::BadRequest(format!(
+                    "{} contains an empty path segment",
+                    url_path
+                )))
+            }
+            // a separator on windows
+            _ if part.contains('\\') => {
+                return Err(LinkerError::BadRequest(format!(
+                    "{} contains a backslash",
+                    url_path
+                )))
+            }
+            "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(LinkerError::OutsideRoot(url_path));
+                }
+            }
+            part => parts.push(part),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+impl RequestedFile {
+    // splits /path/to/file.pdf?page=3#page=4 into its parts. the query wins over the fragment
+    pub fn parse(url: &str) -> Result<RequestedFile, LinkerError> {
+        let (url, fragment) = url.split_once('#').unwrap_or((url, ""));
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        if !path.starts_with('/') {
+            return Err(LinkerError::BadRequest(format!(
+                "{} is not an absolute path",
+                path
+            )));
+        }
+
+        let decoded = percent_decode(path.substring(1, path.len()), false)
+            .map_err(LinkerError::BadRequest)?;
+        let rel_path = normalize(&nfc(&decoded))?;
+        let query = parse_params(query).map_err(LinkerError::BadRequest)?;
+        let page = match find_page(&query).map_err(LinkerError::BadRequest)? {
+            Some(page) => Some(page),
+            None => parse_params(fragment)
+                .and_then(|params| find_page(&params))
+                .map_err(LinkerError::BadRequest)?,
+        };
+
+        Ok(RequestedFile {
+            rel_path,
+            page,
+            query,
+            mount: None,
+        })
+    }
+
+    pub fn url_path(&self) -> String {
+        format!("/{}", self.rel_path)
+    }
+
+    // the path as it was requested, including the mount prefix
+    pub fn full_path(&self) -> String {
+        match &self.mount {
+            Some(mount) => format!("{}/{}", mount, self.rel_path),
+            None => self.rel_path.clone(),
+        }
+    }
+
+    pub fn query_value(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{percent_encode, RequestedFile};
+    use crate::error::LinkerError;
+
+    #[test]
+    fn parses_notion_links() {
+        let requested =
+            RequestedFile::parse("/Vorlesung%20Analysis/U%CC%88bung+1.pdf?page=14&zoom=a+b#page=2")
+                .unwrap();
+        assert_eq!(requested.rel_path, "Vorlesung Analysis/Übung+1.pdf");
+        assert_eq!(requested.page, Some(14));
+        assert_eq!(requested.query_value("zoom"), Some("a b"));
+
+        let requested = RequestedFile::parse("/lecture3.pdf#page=7").unwrap();
+        assert_eq!(requested.rel_path, "lecture3.pdf");
+        assert_eq!(requested.page, Some(7));
+        assert!(requested.query.is_empty());
+
+        let requested = RequestedFile::parse("/lecture3.pdf?page=").unwrap();
+        assert_eq!(requested.page, None);
+        assert_eq!(requested.query_value("page"), Some(""));
+    }
+
+    #[test]
+    fn encodes_paths() {
+        let path = "Vorlesung Analysis/Übung+1 (neu).pdf";
+        let requested = RequestedFile::parse(&format!("/{}", percent_encode(path))).unwrap();
+        assert_eq!(requested.rel_path, path);
+    }
+
+    #[test]
+    fn rejects_malformed_urls() {
+        assert!(RequestedFile::parse("/file%2.pdf").is_err());
+        assert!(RequestedFile::parse("/file%FF.pdf").is_err());
+        assert!(RequestedFile::parse("/file%00.pdf").is_err());
+        assert!(RequestedFile::parse("/file.pdf?page=first").is_err());
+        assert!(RequestedFile::parse("file.pdf").is_err());
+    }
+
+    #[test]
+    fn normalizes_dot_segments() {
+        let requested = RequestedFile::parse("/course/../secret/./x.pdf").unwrap();
+        assert_eq!(requested.rel_path, "secret/x.pdf");
+        assert_eq!(
+            RequestedFile::parse("/course/%2E%2E/%2E%2E/x.pdf").unwrap_err(),
+            LinkerError::OutsideRoot("/course/../../x.pdf".to_string())
+        );
+        assert!(RequestedFile::parse("//etc/passwd").is_err());
+        assert!(RequestedFile::parse("/course//x.pdf").is_err());
+        assert!(RequestedFile::parse("/course/..%5Cx.pdf").is_err());
+    }
+}
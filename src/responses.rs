@@ -0,0 +1,313 @@
+use crate::error::LinkerError;
+use crate::template::expand;
+use serde::{Deserialize, Serialize};
+use std::fs::read_to_string;
+
+// pages shown in the tab that opened the link. each one can be written inline or read from a file:
+// "success: {inline: '<p>opened {{file}}</p>'}" or "failure: {file: ~/failure.html}".
+// variables: {{file}}, {{error}}, {{status}}, {{close_delay_ms}} and {{close_script}}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseTemplates {
+    // the built-in pages close the tab after this delay, if server.close_tab is set
+    #[serde(default = "default_close_delay_ms")]
+    close_delay_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    success: Option<Template>,
+    // the request was not allowed (403, 421 and 429)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    denied: Option<Template>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    failure: Option<Template>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "TemplateEntry", into = "TemplateEntry")]
+pub enum Template {
+    Inline(String),
+    File(String),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inline: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+}
+
+impl TryFrom<TemplateEntry> for Template {
+    type Error = String;
+
+    fn try_from(entry: TemplateEntry) -> Result<Self, Self::Error> {
+        match (entry.inline, entry.file) {
+            (Some(inline), None) => Ok(Template::Inline(inline)),
+            (None, Some(file)) => Ok(Template::File(file)),
+            _ => Err("a response template needs exactly one of inline or file".to_string()),
+        }
+    }
+}
+
+impl From<Template> for TemplateEntry {
+    fn from(template: Template) -> Self {
+        match template {
+            Template::Inline(inline) => TemplateEntry {
+                inline: Some(inline),
+                file: None,
+            },
+            Template::File(file) => TemplateEntry {
+                inline: None,
+                file: Some(file),
+            },
+        }
+    }
+}
+
+fn default_close_delay_ms() -> u64 {
+    1500
+}
+
+impl Default for ResponseTemplates {
+    fn default() -> Self {
+        ResponseTemplates {
+            close_delay_ms: default_close_delay_ms(),
+            success: None,
+            denied: None,
+            failure: None,
+        }
+    }
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>xodo-linker</title>
+<style>
+body { font-family: system-ui, sans-serif; margin: 0; min-height: 100vh; display: flex; align-items: center; justify-content: center; background: #f4f5f7; color: #1f2328; }
+main { max-width: 36em; padding: 2em 2.5em; border-radius: 8px; background: #fff; box-shadow: 0 1px 4px rgba(0, 0, 0, .12); border-top: 4px solid {{color}}; }
+h1 { font-size: 1.25em; margin: 0 0 .5em; }
+p { margin: 0; overflow-wrap: anywhere; }
+</style>
+</head>
+<body>
+<main>
+{{content}}
+</main>
+{{close_script}}
+</body>
+</html>
+"#;
+
+const SUCCESS: &str = "<h1>Opened</h1>\n<p>{{file}}</p>";
+const DENIED: &str = "<h1>Not allowed ({{status}})</h1>\n<p>{{error}}</p>";
+const FAILURE: &str = "<h1>Could not open the file ({{status}})</h1>\n<p>{{error}}</p>";
+
+// browsers do not send the fragment of a url, so file.pdf#page=14 arrives as file.pdf.
+// this page reads it and requests file.pdf?page=14, or file.pdf?page= if there is none
+const FRAGMENT_REDIRECT: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>xodo-linker</title>
+<noscript><meta http-equiv="refresh" content="0; url={{url}}"></noscript>
+</head>
+<body>
+<script>
+const page = /(?:#|&)page=(\d+)/.exec(location.hash);
+const url = new URL(location.href);
+url.hash = "";
+url.searchParams.set("page", page ? page[1] : "");
+location.replace(url);
+</script>
+</body>
+</html>
+"#;
+
+fn escape_html(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+fn is_denied(err: &LinkerError) -> bool {
+    matches!(
+        err,
+        LinkerError::Forbidden(_)
+            | LinkerError::HostNotAllowed(_)
+            | LinkerError::OutsideRoot(_)
+            | LinkerError::SymlinkDenied(_)
+            | LinkerError::RateLimited
+    )
+}
+
+// the page that passes #page=14 on as ?page=14, for a url without a page parameter
+pub fn fragment_redirect(url: &str) -> String {
+    let separator = if url.contains('?') { "&" } else { "?" };
+    let fallback = format!("{}{}page=", url, separator);
+    FRAGMENT_REDIRECT.replace("{{url}}", &escape_html(&fallback))
+}
+
+impl Template {
+    fn load(&self) -> Result<String, String> {
+        match self {
+            Template::Inline(template) => Ok(template.clone()),
+            Template::File(path) => {
+                read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))
+            }
+        }
+    }
+}
+
+impl ResponseTemplates {
+    pub fn expand_placeholders(&mut self) -> Result<(), String> {
+        for template in [&mut self.success, &mut self.denied, &mut self.failure]
+            .into_iter()
+            .flatten()
+        {
+            if let Template::File(path) = template {
+                *path = expand(path, &[])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        [&self.success, &self.denied, &self.failure]
+            .into_iter()
+            .flatten()
+            .filter_map(|template| template.load().err())
+            .collect()
+    }
+
+    fn close_script(&self, close_tab: bool) -> String {
+        if close_tab {
+            format!(
+                "<script>setTimeout(() => window.close(), {})</script>",
+                self.close_delay_ms
+            )
+        } else {
+            String::new()
+        }
+    }
+
+    // the status code and html page for the outcome of a request
+    pub fn render(
+        &self,
+        file: &str,
+        result: &Result<(), LinkerError>,
+        close_tab: bool,
+    ) -> (u16, String) {
+        let (status, error, template, built_in, color, close_tab) = match result {
+            Ok(()) => (
+                200,
+                String::new(),
+                &self.success,
+                SUCCESS,
+                "#2da44e",
+                close_tab,
+            ),
+            Err(err) if is_denied(err) => (
+                err.status_code(),
+                err.message(),
+                &self.denied,
+                DENIED,
+                "#bf8700",
+                false,
+            ),
+            Err(err) => (
+                err.status_code(),
+                err.message(),
+                &self.failure,
+                FAILURE,
+                "#cf222e",
+                false,
+            ),
+        };
+        let page = match template.as_ref().map(Template::load) {
+            Some(Ok(page)) => page,
+            loaded => {
+                if let Some(Err(err)) = loaded {
+                    println!("using built-in response page. {}", err);
+                }
+                PAGE.replace("{{content}}", built_in)
+                    .replace("{{color}}", color)
+            }
+        };
+        let page = page
+            .replace("{{close_script}}", &self.close_script(close_tab))
+            .replace("{{close_delay_ms}}", &self.close_delay_ms.to_string())
+            .replace("{{status}}", &status.to_string())
+            .replace("{{file}}", &escape_html(file))
+            .replace("{{error}}", &escape_html(&error));
+        (status, page)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fragment_redirect, ResponseTemplates};
+    use crate::error::LinkerError;
+
+    #[test]
+    fn renders_built_in_pages() {
+        let templates = ResponseTemplates::default();
+        let (status, page) = templates.render("/tmp/<x>.pdf", &Ok(()), true);
+        assert_eq!(status, 200);
+        assert!(page.contains("<p>/tmp/&lt;x&gt;.pdf</p>"));
+        assert!(page.contains("setTimeout(() => window.close(), 1500)"));
+
+        let (status, page) = templates.render(
+            "/x.pdf",
+            &Err(LinkerError::Forbidden("/x.pdf".to_string())),
+            true,
+        );
+        assert_eq!(status, 403);
+        assert!(page.contains("Not allowed (403)"));
+        assert!(!page.contains("window.close"));
+    }
+
+    #[test]
+    fn renders_configured_templates() {
+        let templates: ResponseTemplates = serde_yaml::from_str(
+            r#"
+close_delay_ms: 0
+success: {inline: "opened {{file}}{{close_script}}"}
+failure: {file: /does/not/exist.html}
+"#,
+        )
+        .unwrap();
+        let (_, page) = templates.render("/x.pdf", &Ok(()), true);
+        assert_eq!(
+            page,
+            "opened /x.pdf<script>setTimeout(() => window.close(), 0)</script>"
+        );
+        // unreadable files fall back to the built-in page
+        let (status, page) = templates.render(
+            "/x.pdf",
+            &Err(LinkerError::NotFound("/x.pdf".to_string())),
+            false,
+        );
+        assert_eq!(status, 404);
+        assert!(page.contains("/x.pdf does not exist"));
+        assert!(serde_yaml::from_str::<ResponseTemplates>("success: {}").is_err());
+    }
+
+    #[test]
+    fn redirects_fragments_to_the_query() {
+        let page = fragment_redirect("/x.pdf?sig=ab&exp=1");
+        assert!(page.contains(r#"content="0; url=/x.pdf?sig=ab&amp;exp=1&amp;page=""#));
+        assert!(page.contains(r#"url.searchParams.set("page", page ? page[1] : "")"#));
+        assert!(fragment_redirect("/x.pdf").contains("url=/x.pdf?page="));
+    }
+}
@@ -0,0 +1,345 @@
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+// a regex, or a glob if prefixed with "glob:". both have to match the whole path.
+// old style blacklist entries match anywhere, like they always did
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Regex(String, Regex),
+    Glob(String, Regex),
+    Unanchored(String, Regex),
+}
+
+// written as "- allow: pattern" or "- deny: pattern"
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RuleEntry", into = "RuleEntry")]
+pub enum Rule {
+    Allow(Pattern),
+    Deny(Pattern),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allow: Option<Pattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deny: Option<Pattern>,
+}
+
+impl TryFrom<RuleEntry> for Rule {
+    type Error = String;
+
+    fn try_from(entry: RuleEntry) -> Result<Self, Self::Error> {
+        match (entry.allow, entry.deny) {
+            (Some(pattern), None) => Ok(Rule::Allow(pattern)),
+            (None, Some(pattern)) => Ok(Rule::Deny(pattern)),
+            _ => Err("a rule needs exactly one of allow or deny".to_string()),
+        }
+    }
+}
+
+impl From<Rule> for RuleEntry {
+    fn from(rule: Rule) -> Self {
+        match rule {
+            Rule::Allow(pattern) => RuleEntry {
+                allow: Some(pattern),
+                deny: None,
+            },
+            Rule::Deny(pattern) => RuleEntry {
+                allow: None,
+                deny: Some(pattern),
+            },
+        }
+    }
+}
+
+// the rules of the security section or of a mount. blacklist and whitelist are the old style lists,
+// they are read as allow rules for the whitelist followed by deny rules for the blacklist
+pub struct AccessRules<'a> {
+    pub rules: &'a [Rule],
+    pub default_action: Option<Action>,
+    pub blacklist: &'a [Pattern],
+    pub whitelist: &'a [Pattern],
+}
+
+#[derive(Debug)]
+pub struct Decision {
+    pub action: Action,
+    pub rule: Option<Rule>,
+}
+
+fn glob_to_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let mut regex = String::from("^");
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            regex.push_str("(?:.*/)?");
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = after;
+            continue;
+        }
+        match c {
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    regex.push('$');
+    Regex::new(&regex)
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Pattern, regex::Error> {
+        match pattern.strip_prefix("glob:") {
+            Some(glob) => Ok(Pattern::Glob(glob.to_string(), glob_to_regex(glob)?)),
+            None => Ok(Pattern::Regex(
+                pattern.to_string(),
+                Regex::new(&format!("^(?:{})$", pattern))?,
+            )),
+        }
+    }
+
+    // "\\.exe" still denies /setup.exe. anchoring it would let a blacklist-only config allow it
+    pub fn parse_unanchored(pattern: &str) -> Result<Pattern, regex::Error> {
+        match pattern.strip_prefix("glob:") {
+            Some(_) => Pattern::parse(pattern),
+            None => Ok(Pattern::Unanchored(
+                pattern.to_string(),
+                Regex::new(pattern)?,
+            )),
+        }
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        match self {
+            Pattern::Regex(_, regex) | Pattern::Glob(_, regex) | Pattern::Unanchored(_, regex) => {
+                regex.is_match(path)
+            }
+        }
+    }
+}
+
+// deserializes the old style blacklist
+pub fn unanchored<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Pattern>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| Pattern::parse_unanchored(pattern).map_err(de::Error::custom))
+        .collect()
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Regex(regex, _) | Pattern::Unanchored(regex, _) => write!(f, "{}", regex),
+            Pattern::Glob(glob, _) => write!(f, "glob:{}", glob),
+        }
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Pattern::parse(&pattern).map_err(de::Error::custom)
+    }
+}
+
+impl Rule {
+    pub fn action(&self) -> Action {
+        match self {
+            Rule::Allow(_) => Action::Allow,
+            Rule::Deny(_) => Action::Deny,
+        }
+    }
+
+    pub fn pattern(&self) -> &Pattern {
+        match self {
+            Rule::Allow(pattern) | Rule::Deny(pattern) => pattern,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::Allow(pattern) => write!(f, "allow {}", pattern),
+            Rule::Deny(pattern) => write!(f, "deny {}", pattern),
+        }
+    }
+}
+
+impl AccessRules<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+            && self.default_action.is_none()
+            && self.blacklist.is_empty()
+            && self.whitelist.is_empty()
+    }
+
+    // a blacklist on its own used to allow everything else, anything else denies by default,
+    // including no rules at all
+    fn default_action(&self) -> Action {
+        match self.default_action {
+            Some(action) => action,
+            None if self.rules.is_empty()
+                && self.whitelist.is_empty()
+                && !self.blacklist.is_empty() =>
+            {
+                Action::Allow
+            }
+            None => Action::Deny,
+        }
+    }
+
+    // rel_path is the decoded path inside the mount, e.g. "course/x.pdf".
+    // the old style lists were matched against the url and still see it with a leading slash
+    pub fn check(&self, rel_path: &str) -> Decision {
+        let url_path = format!("/{}", rel_path);
+        let rules = self.rules.iter().cloned().map(|rule| (rule, rel_path));
+        let legacy_rules = self
+            .whitelist
+            .iter()
+            .map(|p| Rule::Allow(p.clone()))
+            .chain(self.blacklist.iter().map(|p| Rule::Deny(p.clone())))
+            .map(|rule| (rule, url_path.as_str()));
+        match rules
+            .chain(legacy_rules)
+            .find(|(rule, path)| rule.pattern().is_match(path))
+            .map(|(rule, _)| rule)
+        {
+            Some(rule) => Decision {
+                action: rule.action(),
+                rule: Some(rule),
+            },
+            None => Decision {
+                action: self.default_action(),
+                rule: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self.action {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+        };
+        match &self.rule {
+            Some(rule) => write!(f, "{} (rule: {})", action, rule),
+            None => write!(f, "{} (default action)", action),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AccessRules, Action, Pattern, Rule};
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Lists {
+        #[serde(default)]
+        rules: Vec<Rule>,
+        #[serde(default)]
+        default_action: Option<Action>,
+        #[serde(default, deserialize_with = "super::unanchored")]
+        blacklist: Vec<Pattern>,
+        #[serde(default)]
+        whitelist: Vec<Pattern>,
+    }
+
+    impl Lists {
+        fn action(&self, rel_path: &str) -> Action {
+            AccessRules {
+                rules: &self.rules,
+                default_action: self.default_action,
+                blacklist: &self.blacklist,
+                whitelist: &self.whitelist,
+            }
+            .check(rel_path)
+            .action
+        }
+    }
+
+    fn rules(yaml: &str) -> Lists {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let rules = rules(
+            r#"
+rules:
+    - deny: "secret/.*"
+    - allow: "glob:**/*.pdf"
+default_action: deny
+"#,
+        );
+        assert_eq!(rules.action("course/x.pdf"), Action::Allow);
+        assert_eq!(rules.action("x.pdf"), Action::Allow);
+        assert_eq!(rules.action("secret/x.pdf"), Action::Deny);
+        assert_eq!(rules.action("setup.exe"), Action::Deny);
+    }
+
+    #[test]
+    fn anchors_regexes() {
+        let rules = rules(r#"rules: [allow: ".*\\.pdf", allow: "notes"]"#);
+        assert_eq!(rules.action("course/x.pdf"), Action::Allow);
+        assert_eq!(rules.action("evil.pdf.exe"), Action::Deny);
+        assert_eq!(rules.action("x.pdfx"), Action::Deny);
+        assert_eq!(rules.action("notes"), Action::Allow);
+        assert_eq!(rules.action("my-notes.md"), Action::Deny);
+    }
+
+    #[test]
+    fn reads_legacy_lists() {
+        let lists = rules(r#"{blacklist: ["/favicon\\.ico"], whitelist: [".*\\.pdf"]}"#);
+        assert_eq!(lists.action("x.pdf"), Action::Allow);
+        assert_eq!(lists.action("favicon.ico"), Action::Deny);
+        assert_eq!(lists.action("secret.exe"), Action::Deny);
+        assert_eq!(lists.action("evil.pdf.exe"), Action::Deny);
+
+        let blacklist_only = rules(r#"blacklist: ["/favicon\\.ico"]"#);
+        assert_eq!(blacklist_only.action("favicon.ico"), Action::Deny);
+        assert_eq!(blacklist_only.action("x.pdf"), Action::Allow);
+
+        // old style blacklist entries match anywhere in the path
+        let unanchored = rules(r#"blacklist: ["\\.exe"]"#);
+        assert_eq!(unanchored.action("setup.exe"), Action::Deny);
+        assert_eq!(unanchored.action("tools/setup.exe"), Action::Deny);
+        assert_eq!(unanchored.action("x.pdf"), Action::Allow);
+
+        let nothing = rules("{}");
+        assert_eq!(nothing.action("x.pdf"), Action::Deny);
+    }
+
+    #[test]
+    fn matches_globs() {
+        let rules = rules(r#"rules: [allow: "glob:course/*.pdf", allow: "glob:**/notes-?.md"]"#);
+        assert_eq!(rules.action("course/x.pdf"), Action::Allow);
+        assert_eq!(rules.action("course/sub/x.pdf"), Action::Deny);
+        assert_eq!(rules.action("a/b/notes-1.md"), Action::Allow);
+        assert_eq!(rules.action("notes-1.md"), Action::Allow);
+        assert_eq!(rules.action("notes-12.md"), Action::Deny);
+    }
+}
@@ -0,0 +1,358 @@
+use crate::config::Layers;
+use crate::error::LinkerError;
+use crate::limits::Limits;
+use crate::linker::Linker;
+use crate::shutdown::signalled;
+use crate::workers::WorkerPool;
+use std::fs::metadata;
+use std::io::{stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use tiny_http::{Request, Server};
+
+// how often the server loop checks whether it should stop or the config file changed
+const POLL: Duration = Duration::from_millis(250);
+
+// the running server. the configuration is swapped as a whole when the config file changes,
+// requests that already started keep the configuration they started with
+pub struct Service {
+    linker: RwLock<Arc<Linker>>,
+    // where the configuration came from, None disables reloading
+    layers: Option<Layers>,
+    modified: Mutex<Option<(SystemTime, u64)>>,
+    // rate limit buckets and recent launches, they outlive a reload
+    limits: Limits,
+    stopping: AtomicBool,
+}
+
+// the listening sockets. each one has a thread that passes its requests on to the server loop
+struct Listeners {
+    requests: Sender<Request>,
+    stopping: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Listeners {
+    fn new(requests: Sender<Request>) -> Listeners {
+        Listeners {
+            requests,
+            stopping: Arc::new(AtomicBool::new(false)),
+            threads: vec![],
+        }
+    }
+
+    fn listen(&mut self, servers: Vec<Server>) {
+        for server in servers {
+            println!("Listening on {}", server.server_addr());
+            let requests = self.requests.clone();
+            let stopping = Arc::clone(&self.stopping);
+            self.threads.push(thread::spawn(move || {
+                while !stopping.load(Ordering::SeqCst) {
+                    match server.recv_timeout(POLL) {
+                        Ok(Some(request)) => {
+                            if requests.send(request).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(err) => println!("could not receive request: {}", err),
+                    }
+                }
+            }));
+        }
+    }
+
+    // closes all sockets, requests that were already received are still handled
+    fn stop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        self.stopping = Arc::new(AtomicBool::new(false));
+    }
+}
+
+fn modified(layers: &Layers) -> Option<(SystemTime, u64)> {
+    let metadata = metadata(layers.file.as_ref()?).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+impl Service {
+    pub fn new(linker: Linker, layers: Option<Layers>) -> Arc<Service> {
+        let modified = layers.as_ref().and_then(modified);
+        Arc::new(Service {
+            linker: RwLock::new(Arc::new(linker)),
+            layers,
+            modified: Mutex::new(modified),
+            limits: Limits::default(),
+            stopping: AtomicBool::new(false),
+        })
+    }
+
+    pub fn linker(&self) -> Arc<Linker> {
+        Arc::clone(&self.linker.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    // stops the server loop, requests that are already running are finished first
+    pub fn shutdown(&self) {
+        println!("shutdown requested");
+        self.stopping.store(true, Ordering::SeqCst);
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst) || signalled()
+    }
+
+    // the new configuration if the config file changed and is valid
+    fn changed_config(&self) -> Option<Linker> {
+        let layers = self.layers.as_ref()?;
+        let modified = modified(layers);
+        {
+            let mut last = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+            if *last == modified {
+                return None;
+            }
+            *last = modified;
+        }
+        match Linker::load_config(layers) {
+            Ok(linker) => Some(linker),
+            Err(err) => {
+                println!("Keeping the previous configuration. {}", err);
+                None
+            }
+        }
+    }
+
+    // swaps in a changed configuration. the sockets are only replaced if the addresses changed.
+    // the old ones are closed first, the new addresses may share a port with them
+    fn reload(&self, listeners: &mut Listeners) {
+        let linker = match self.changed_config() {
+            Some(linker) => linker,
+            None => return,
+        };
+        let current = self.linker();
+        if !linker.same_address(&current) {
+            listeners.stop();
+            match linker.listen() {
+                Ok(servers) => listeners.listen(servers),
+                Err(err) => {
+                    println!("Keeping the previous configuration. {}", err);
+                    match current.listen() {
+                        Ok(servers) => listeners.listen(servers),
+                        Err(err) => println!("could not listen again: {}", err),
+                    }
+                    return;
+                }
+            }
+        }
+        *self.linker.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(linker);
+        println!("Reloaded configuration.");
+    }
+
+    fn handle_request(&self, request: tiny_http::Request) {
+        if self.linker().handle_request(request, &self.limits) {
+            self.shutdown();
+        }
+    }
+
+    pub fn start(self: &Arc<Self>) -> Result<(), LinkerError> {
+        let servers = self.linker().listen()?;
+        self.serve(servers)
+    }
+
+    // handles requests until shutdown is called, /__shutdown is requested or a signal arrives.
+    // server.workers only applies when the server is started
+    pub fn serve(self: &Arc<Self>, servers: Vec<Server>) -> Result<(), LinkerError> {
+        let workers = self.linker().server.workers();
+        let pool = WorkerPool::new(workers);
+        let (sender, requests) = channel();
+        let mut listeners = Listeners::new(sender);
+        listeners.listen(servers);
+        println!("Started server with {} workers", workers);
+        let mut checked = Instant::now();
+        while !self.is_stopping() {
+            // the listeners keep a sender, so this only times out
+            if let Ok(request) = requests.recv_timeout(POLL) {
+                println!("Received request.");
+                let service = Arc::clone(self);
+                pool.execute(move || service.handle_request(request));
+            }
+            if checked.elapsed() >= POLL {
+                self.reload(&mut listeners);
+                checked = Instant::now();
+            }
+        }
+        println!("Stopping server. Waiting for running requests");
+        listeners.stop();
+        drop(pool);
+        println!("Stopped server.");
+        let _ = stdout().flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(unix)]
+    use super::Listeners;
+    use super::Service;
+    use crate::linker::Linker;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    #[cfg(unix)]
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use tiny_http::Server;
+
+    fn send(addr: SocketAddr, host: &str, method: &str, path: &str) -> String {
+        send_with(addr, host, method, path, "")
+    }
+
+    fn send_with(addr: SocketAddr, host: &str, method: &str, path: &str, headers: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n",
+            method, path, host, headers
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn stops_on_shutdown_request() {
+        let service = Service::new(Linker::default(), None);
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let serving = {
+            let service = service.clone();
+            thread::spawn(move || service.serve(vec![server]))
+        };
+
+        assert!(send(addr, "localhost", "GET", "/__shutdown").starts_with("HTTP/1.1 405"));
+        // dns rebinding, another site pointed its name to this machine
+        assert!(send(addr, "evil.example", "POST", "/__shutdown").starts_with("HTTP/1.1 421"));
+        // a form on another site that posts to the server
+        let origin = "Origin: https://evil.example\r\nSec-Fetch-Site: cross-site\r\n";
+        assert!(
+            send_with(addr, "localhost", "POST", "/__shutdown", origin).starts_with("HTTP/1.1 403")
+        );
+        assert!(send(addr, "127.0.0.1:80", "POST", "/__shutdown").starts_with("HTTP/1.1 200"));
+        assert_eq!(serving.join().unwrap(), Ok(()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retries_failed_launches() {
+        use std::fs::{create_dir_all, remove_dir_all, File};
+
+        let dir = std::env::temp_dir().join(format!("xodo-linker-{}-retry", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        File::create(dir.join("a.pdf")).unwrap();
+        let linker: Linker = serde_yaml::from_str(&format!(
+            "security: {{rules: [allow: \"glob:*.pdf\"]}}\nserver: {{close_tab: false}}\nsystem:\n  hostname: xodo\n  base_path: {}\n  wait_for_exit: true\n  launcher: {{type: custom, program: \"false\"}}",
+            dir.display()
+        ))
+        .unwrap();
+        let service = Service::new(linker, None);
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let serving = {
+            let service = service.clone();
+            thread::spawn(move || service.serve(vec![server]))
+        };
+
+        // links without a page parameter are sent back with the page from the fragment
+        assert!(send(addr, "localhost", "GET", "/a.pdf").contains("location.replace(url)"));
+        // only requests that pass the checks get it
+        assert!(send(addr, "localhost", "GET", "/a.txt").starts_with("HTTP/1.1 403"));
+        assert!(send(addr, "localhost", "GET", "/a.pdf?page=").starts_with("HTTP/1.1 502"));
+        // the retry is launched again instead of being debounced
+        assert!(send(addr, "localhost", "GET", "/a.pdf?page=").starts_with("HTTP/1.1 502"));
+        // only GET opens files
+        assert!(send(addr, "localhost", "HEAD", "/a.pdf?page=").starts_with("HTTP/1.1 405"));
+        assert!(send(addr, "localhost", "POST", "/a.pdf?page=").starts_with("HTTP/1.1 405"));
+        assert!(send(addr, "localhost", "POST", "/__shutdown").starts_with("HTTP/1.1 200"));
+        assert_eq!(serving.join().unwrap(), Ok(()));
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serves_unix_sockets() {
+        use crate::listen::ListenAddr;
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("xodo-linker-{}.sock", std::process::id()));
+        let unix = ListenAddr::Unix(path.clone()).bind().unwrap();
+        let tcp = ListenAddr::Tcp("127.0.0.1:0".to_string()).bind().unwrap();
+        let addr = tcp.server_addr().to_ip().unwrap();
+        let service = Service::new(Linker::default(), None);
+        let serving = {
+            let service = service.clone();
+            thread::spawn(move || service.serve(vec![unix, tcp]))
+        };
+
+        assert!(send(addr, "localhost", "GET", "/__shutdown").starts_with("HTTP/1.1 405"));
+        // force_loopback does not apply, there is no client address
+        let mut stream = UnixStream::connect(&path).unwrap();
+        write!(
+            stream,
+            "POST /__shutdown HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert_eq!(serving.join().unwrap(), Ok(()));
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reloads_valid_config() {
+        use crate::config::Layers;
+        use crate::rules::Action;
+        use std::fs::{create_dir_all, remove_dir_all, write};
+
+        let dir = std::env::temp_dir().join(format!("xodo-linker-{}-reload", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("config.yaml");
+        let config = include_str!("config.yaml")
+            .replace(r"{{home_dir}}\\OneDrive\\ONEDRI~1", dir.to_str().unwrap());
+        write(&path, &config).unwrap();
+        let layers = Layers {
+            file: Some(path.clone()),
+            ..Layers::default()
+        };
+        let service = Service::new(Linker::load_config(&layers).unwrap(), Some(layers));
+        let (sender, _requests) = channel();
+        let mut listeners = Listeners::new(sender);
+        let action = |service: &Service| {
+            service
+                .linker()
+                .resolve("/notes.txt")
+                .unwrap()
+                .decision
+                .action
+        };
+        assert_eq!(action(&service), Action::Deny);
+
+        let allowed = config.replace(r#"- deny: "favicon\\.ico""#, r#"- allow: "glob:*.txt""#);
+        write(&path, &allowed).unwrap();
+        service.reload(&mut listeners);
+        assert_eq!(action(&service), Action::Allow);
+
+        // an invalid edit keeps the previous configuration
+        write(&path, allowed.replace("server:", "servers:")).unwrap();
+        service.reload(&mut listeners);
+        assert_eq!(action(&service), Action::Allow);
+
+        remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// set from the signal handler, polled by the server loop. a signal handler may not do much more
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_signal(_: libc::c_int) {
+    SIGNALLED.store(true, Ordering::SeqCst);
+}
+
+// SIGINT (ctrl+c) and SIGTERM stop the server gracefully instead of killing it
+#[cfg(unix)]
+pub fn install_signal_handlers() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: on_signal only stores to an atomic, which is async-signal-safe
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            println!("could not install handler for signal {}", signal);
+        }
+    }
+}
+
+#[cfg(windows)]
+extern "system" {
+    fn SetConsoleCtrlHandler(
+        handler: Option<unsafe extern "system" fn(u32) -> i32>,
+        add: i32,
+    ) -> i32;
+}
+
+// runs on its own thread. returning true tells windows the event was handled
+#[cfg(windows)]
+unsafe extern "system" fn on_ctrl(_: u32) -> i32 {
+    SIGNALLED.store(true, Ordering::SeqCst);
+    1
+}
+
+// ctrl+c, ctrl+break and closing the console stop the server gracefully
+#[cfg(windows)]
+pub fn install_signal_handlers() {
+    // SAFETY: on_ctrl only stores to an atomic
+    if unsafe { SetConsoleCtrlHandler(Some(on_ctrl), 1) } == 0 {
+        println!("could not install the console control handler");
+    }
+}
+
+pub fn signalled() -> bool {
+    SIGNALLED.load(Ordering::SeqCst)
+}
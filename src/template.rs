@@ -0,0 +1,91 @@
+use dirs::{config_dir, document_dir, download_dir, home_dir};
+use std::env;
+use std::path::PathBuf;
+
+// placeholders that are filled in when a file is launched, not when the config is loaded
+pub const LAUNCH_PLACEHOLDERS: &[&str] = &["path", "page"];
+
+fn dir_to_string(dir: Option<PathBuf>, name: &str) -> Result<String, String> {
+    dir.ok_or(format!("could not find {}", name))?
+        .to_str()
+        .map(|dir| dir.to_string())
+        .ok_or(format!("{} is not valid utf-8", name))
+}
+
+fn lookup(name: &str) -> Result<String, String> {
+    if let Some(var) = name.strip_prefix("env:") {
+        return env::var(var).map_err(|e| format!("environment variable {}: {}", var, e));
+    }
+    match name {
+        "home_dir" => dir_to_string(home_dir(), name),
+        "config_dir" => dir_to_string(config_dir(), name),
+        "document_dir" => dir_to_string(document_dir(), name),
+        "download_dir" => dir_to_string(download_dir(), name),
+        _ => Err(format!("unknown placeholder {{{{{}}}}}", name)),
+    }
+}
+
+// replaces "~" at the start and every {{placeholder}} except the ones in keep
+pub fn expand(input: &str, keep: &[&str]) -> Result<String, String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        output.push_str(&lookup("home_dir")?);
+        rest = &rest[1..];
+    }
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .ok_or(format!("unclosed placeholder in {}", input))?
+            + start;
+        let name = rest[start + 2..end].trim();
+        if keep.contains(&name) {
+            output.push_str(&rest[start..end + 2]);
+        } else {
+            output.push_str(&lookup(name).map_err(|e| format!("{} in {}", e, input))?);
+        }
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+pub fn expand_all(inputs: &mut [String], keep: &[&str]) -> Result<(), String> {
+    for input in inputs.iter_mut() {
+        *input = expand(input, keep)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expand, LAUNCH_PLACEHOLDERS};
+    use dirs::home_dir;
+
+    #[test]
+    fn expands_placeholders() {
+        let home = home_dir().unwrap();
+        let home = home.to_str().unwrap();
+        std::env::set_var("XODO_LINKER_TEST_DIR", "Papers");
+
+        assert_eq!(expand("~/x", &[]).unwrap(), format!("{}/x", home));
+        assert_eq!(expand("a~b", &[]).unwrap(), "a~b");
+        assert_eq!(
+            expand("{{home_dir}}/{{ env:XODO_LINKER_TEST_DIR }}", &[]).unwrap(),
+            format!("{}/Papers", home)
+        );
+        assert_eq!(
+            expand("--page={{page}} {{path}}", LAUNCH_PLACEHOLDERS).unwrap(),
+            "--page={{page}} {{path}}"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        assert!(expand("{{home}}/OneDrive", &[]).is_err());
+        assert!(expand("{{path}}", &[]).is_err());
+        assert!(expand("{{home_dir", &[]).is_err());
+        assert!(expand("{{env:XODO_LINKER_TEST_UNSET}}", &[]).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use crate::error::LinkerError;
+use crate::template::expand;
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+use tiny_http::Server;
+
+// browsers only trust self-signed certificates for up to 825 days
+#[cfg(feature = "tls")]
+const CERT_DAYS: i64 = 825;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    // pem files, gen-cert creates them
+    #[serde(default = "default_cert")]
+    pub cert: String,
+    #[serde(default = "default_key")]
+    pub key: String,
+    // https on this port and plain http on server.port. server.port serves https if not set
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+fn default_cert() -> String {
+    "{{config_dir}}/xodo-linker/cert.pem".to_string()
+}
+
+fn default_key() -> String {
+    "{{config_dir}}/xodo-linker/key.pem".to_string()
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert: default_cert(),
+            key: default_key(),
+            port: None,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn expand_placeholders(&mut self) -> Result<(), String> {
+        self.cert = expand(&self.cert, &[])?;
+        self.key = expand(&self.key, &[])?;
+        Ok(())
+    }
+
+    // missing files are only reported when listening, gen-cert needs the configuration to create them
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if !cfg!(feature = "tls") {
+            problems.push("server.tls needs a build with --features tls".to_string());
+        }
+        if self.port == Some(0) {
+            problems.push("server.tls.port can not be 0".to_string());
+        }
+        problems
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn listen(&self, addr: &str, port: u16) -> Result<Server, LinkerError> {
+        use std::fs::read;
+        use tiny_http::SslConfig;
+
+        let read = |path: &str| {
+            read(path).map_err(|e| {
+                LinkerError::ConfigError(format!(
+                    "could not read {}, create it with gen-cert: {}",
+                    path, e
+                ))
+            })
+        };
+        let config = SslConfig {
+            certificate: read(&self.cert)?,
+            private_key: read(&self.key)?,
+        };
+        Server::https((addr, port), config).map_err(|e| {
+            LinkerError::ConfigError(format!(
+                "could not listen on {}:{} with tls: {}",
+                addr, port, e
+            ))
+        })
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub fn listen(&self, _addr: &str, _port: u16) -> Result<Server, LinkerError> {
+        Err(LinkerError::ConfigError(
+            "server.tls needs a build with --features tls".to_string(),
+        ))
+    }
+
+    // a self-signed certificate for hostname and localhost
+    pub fn gen_cert(&self, hostname: &str) -> Result<(), String> {
+        for path in [&self.cert, &self.key] {
+            if Path::new(path).exists() {
+                return Err(format!(
+                    "{} already exists, remove it to create a new certificate",
+                    path
+                ));
+            }
+            if let Some(dir) = Path::new(path).parent() {
+                create_dir_all(dir)
+                    .map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+            }
+        }
+        let (cert, key) = self_signed(hostname)?;
+        write(&self.cert, cert).map_err(|e| format!("could not write {}: {}", self.cert, e))?;
+        write_private(&self.key, &key).map_err(|e| format!("could not write {}: {}", self.key, e))
+    }
+}
+
+// only the owner may read the key on unix. on windows the profile directory already is private
+#[cfg(unix)]
+fn write_private(path: &str, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &str, contents: &str) -> std::io::Result<()> {
+    write(path, contents)
+}
+
+// the certificate and private key as pem
+#[cfg(feature = "tls")]
+fn self_signed(hostname: &str) -> Result<(String, String), String> {
+    use rcgen::{Certificate, CertificateParams, DistinguishedName, DnType, SanType};
+    use std::net::IpAddr;
+    use time::{Duration, OffsetDateTime};
+
+    let mut params = CertificateParams::default();
+    params.subject_alt_names = [hostname, "localhost", "127.0.0.1", "::1"]
+        .iter()
+        .map(|name| match name.parse::<IpAddr>() {
+            Ok(ip) => SanType::IpAddress(ip),
+            Err(_) => SanType::DnsName(name.to_string()),
+        })
+        .collect();
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, hostname);
+    params.not_before = OffsetDateTime::now_utc() - Duration::days(1);
+    params.not_after = params.not_before + Duration::days(CERT_DAYS);
+    let cert = Certificate::from_params(params)
+        .map_err(|e| format!("could not create a certificate: {}", e))?;
+    let pem = cert
+        .serialize_pem()
+        .map_err(|e| format!("could not create a certificate: {}", e))?;
+    Ok((pem, cert.serialize_private_key_pem()))
+}
+
+#[cfg(not(feature = "tls"))]
+fn self_signed(_hostname: &str) -> Result<(String, String), String> {
+    Err("gen-cert needs a build with --features tls".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::TlsConfig;
+    use std::fs::{read_to_string, remove_dir_all};
+
+    #[test]
+    fn generates_certificate() {
+        let dir = std::env::temp_dir().join(format!("xodo-linker-{}-tls", std::process::id()));
+        let tls = TlsConfig {
+            cert: dir.join("cert.pem").to_str().unwrap().to_string(),
+            key: dir.join("key.pem").to_str().unwrap().to_string(),
+            port: Some(443),
+        };
+        if cfg!(not(feature = "tls")) {
+            assert!(tls.gen_cert("xodo").unwrap_err().contains("--features tls"));
+            let _ = remove_dir_all(dir);
+            return;
+        }
+        assert!(tls.validate().is_empty());
+        tls.gen_cert("xodo").unwrap();
+        // existing files are not overwritten
+        assert!(tls.gen_cert("xodo").is_err());
+
+        assert!(read_to_string(&tls.cert)
+            .unwrap()
+            .starts_with("-----BEGIN CERTIFICATE-----"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&tls.key).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(tls.listen("127.0.0.1", 0).is_ok());
+        remove_dir_all(dir).unwrap();
+    }
+}
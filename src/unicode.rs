@@ -0,0 +1,26 @@
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+// OneDrive and macOS store "ü" as "u" + combining diaeresis (NFD), browsers send it composed (NFC).
+// file names are compared in NFC
+pub fn nfc(input: &str) -> String {
+    match is_nfc_quick(input.chars()) {
+        IsNormalized::Yes => input.to_string(),
+        _ => input.nfc().collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::nfc;
+
+    #[test]
+    fn normalizes_to_nfc() {
+        let composed = "Übungsblatt Lösung.pdf";
+        assert_eq!(nfc("U\u{308}bungsblatt Lo\u{308}sung.pdf"), composed);
+        assert_eq!(nfc(composed), composed);
+        assert_eq!(nfc("\u{1EA0}\u{302}"), "\u{1EAC}");
+        // combining marks are reordered before they are composed
+        assert_eq!(nfc("a\u{323}\u{302}"), nfc("a\u{302}\u{323}"));
+        assert_eq!(nfc("\u{1100}\u{1161}\u{11A8}"), "\u{AC01}");
+    }
+}
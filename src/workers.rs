@@ -0,0 +1,97 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// a fixed number of threads that take jobs from a queue. execute blocks while all of them are
+// busy and the queue is full, so a flood of requests does not pile up unbounded
+pub struct WorkerPool {
+    sender: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+fn work(id: usize, receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        // the lock is only held while waiting for the next job, not while running it
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(err) => err.into_inner().recv(),
+        };
+        match job {
+            Ok(job) => {
+                // a panicking job must not take the worker down with it
+                if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                    println!("worker {} recovered from a panic", id);
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    println!("worker {} stopped", id);
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> WorkerPool {
+        let size = size.max(1);
+        let (sender, receiver) = sync_channel::<Job>(size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("worker-{}", id))
+                    .spawn(move || work(id, receiver))
+                    .expect("expected worker thread to start")
+            })
+            .collect();
+        WorkerPool {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        if let Some(sender) = &self.sender {
+            if sender.send(Box::new(job)).is_err() {
+                println!("could not queue job, all workers stopped");
+            }
+        }
+    }
+}
+
+// waits for the queued jobs to finish
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WorkerPool;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+
+    #[test]
+    fn runs_jobs_in_parallel() {
+        let pool = WorkerPool::new(3);
+        // only passes if all three jobs run at the same time
+        let barrier = Arc::new(Barrier::new(3));
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let barrier = Arc::clone(&barrier);
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                barrier.wait();
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        drop(pool);
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+    }
+}